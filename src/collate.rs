@@ -0,0 +1,27 @@
+//! Sorting names by the collation rules of the user's locale.
+
+use std::ffi::CString;
+use std::sync::Once;
+
+static INIT: Once = Once::new();
+
+/// Returns a key for `text` that sorts, compared bytewise, the way the
+/// `LC_COLLATE` locale sorts `text`.
+pub fn sort_key(text: &[u8]) -> Vec<u8> {
+    INIT.call_once(|| unsafe {
+        libc::setlocale(libc::LC_COLLATE, b"\0".as_ptr() as *const libc::c_char);
+    });
+    let text = match CString::new(text) {
+        Ok(text) => text,
+        Err(_) => return text.to_vec(),
+    };
+    let mut key = vec![0u8; text.as_bytes().len() * 4 + 1];
+    loop {
+        let len = unsafe { libc::strxfrm(key.as_mut_ptr() as *mut _, text.as_ptr(), key.len()) };
+        if len < key.len() {
+            key.truncate(len);
+            return key;
+        }
+        key.resize(len + 1, 0);
+    }
+}
@@ -0,0 +1,395 @@
+//! Editing file attributes in columns shown before the names in the editor.
+
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::io;
+use std::mem::MaybeUninit;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::Path;
+
+use crate::copy;
+
+/// An attribute shown in a column of the editor buffer.
+#[derive(Clone, PartialEq)]
+pub enum Column {
+    /// Permissions in octal.
+    Mode,
+    /// Owner and group as `user:group`.
+    Owner,
+    /// Modification time in ISO 8601 format.
+    Time,
+    /// The text value of the named extended attribute, such as
+    /// `user.xdg.tags`.
+    Xattr(String),
+}
+
+impl Column {
+    pub fn name(&self) -> &str {
+        match self {
+            Column::Mode => "mode",
+            Column::Owner => "owner",
+            Column::Time => "modification time",
+            Column::Xattr(name) => name,
+        }
+    }
+
+    /// Returns the current value of the attribute of `path`. Attributes that
+    /// cannot be edited for a file are shown as `-`.
+    pub fn read(&self, path: &Path) -> io::Result<String> {
+        let metadata = fs::symlink_metadata(path)?;
+        Ok(match self {
+            Column::Mode if metadata.file_type().is_symlink() => "-".into(),
+            Column::Mode => format!("{:04o}", metadata.mode() & 0o7777),
+            Column::Owner => format!(
+                "{}:{}",
+                user_name(metadata.uid()),
+                group_name(metadata.gid())
+            ),
+            Column::Time => format_time(metadata.mtime()),
+            // User attributes are not supported on symbolic links.
+            Column::Xattr(_) if metadata.file_type().is_symlink() => "-".into(),
+            Column::Xattr(name) => read_xattr(path, name)?,
+        })
+    }
+
+    /// Returns true if `value` is a valid value for the attribute.
+    pub fn is_valid(&self, value: &str) -> bool {
+        match self {
+            Column::Mode => parse_mode(value).is_some(),
+            Column::Owner => parse_owner(value).is_some(),
+            Column::Time => parse_time(value).is_some(),
+            Column::Xattr(_) => value != "-",
+        }
+    }
+
+    /// Sets the attribute of `path` to `value`, which must be valid.
+    pub fn apply(&self, path: &Path, value: &str) -> io::Result<()> {
+        match self {
+            Column::Mode => match parse_mode(value) {
+                Some(mode) => fs::set_permissions(path, fs::Permissions::from_mode(mode)),
+                None => Ok(()),
+            },
+            Column::Owner => match parse_owner(value) {
+                Some((uid, gid)) => {
+                    let path = CString::new(path.as_os_str().as_bytes())?;
+                    if unsafe { libc::lchown(path.as_ptr(), uid, gid) } == -1 {
+                        return Err(io::Error::last_os_error());
+                    }
+                    Ok(())
+                }
+                None => Ok(()),
+            },
+            Column::Time => match parse_time(value) {
+                Some(time) => {
+                    let path = CString::new(path.as_os_str().as_bytes())?;
+                    let times = [
+                        libc::timespec {
+                            tv_sec: 0,
+                            tv_nsec: libc::UTIME_OMIT,
+                        },
+                        libc::timespec {
+                            tv_sec: time as libc::time_t,
+                            tv_nsec: 0,
+                        },
+                    ];
+                    if unsafe {
+                        libc::utimensat(
+                            libc::AT_FDCWD,
+                            path.as_ptr(),
+                            times.as_ptr(),
+                            libc::AT_SYMLINK_NOFOLLOW,
+                        )
+                    } == -1
+                    {
+                        return Err(io::Error::last_os_error());
+                    }
+                    Ok(())
+                }
+                None => Ok(()),
+            },
+            Column::Xattr(name) => write_xattr(path, name, value),
+        }
+    }
+}
+
+/// Returns the value of the extended attribute `name` of `path`, an empty
+/// string if it is not set, or `-` if the value cannot be edited as a line of
+/// text.
+#[cfg(target_os = "linux")]
+fn read_xattr(path: &Path, name: &str) -> io::Result<String> {
+    let name = CString::new(name)?;
+    match copy::get_xattr(&copy::c_path(path)?, &name) {
+        Ok(value) => Ok(match String::from_utf8(value) {
+            Ok(value) if !value.contains(['\t', '\n']) && value != "-" => value,
+            _ => "-".into(),
+        }),
+        Err(err) if err.raw_os_error() == Some(libc::ENODATA) => Ok(String::new()),
+        Err(err) if err.raw_os_error() == Some(libc::ENOTSUP) => Ok("-".into()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Sets the extended attribute `name` of `path` to `value` or removes it if
+/// `value` is empty.
+#[cfg(target_os = "linux")]
+fn write_xattr(path: &Path, name: &str, value: &str) -> io::Result<()> {
+    let path = copy::c_path(path)?;
+    let name = CString::new(name)?;
+    if value.is_empty() {
+        match copy::remove_xattr(&path, &name) {
+            Err(err) if err.raw_os_error() == Some(libc::ENODATA) => Ok(()),
+            result => result,
+        }
+    } else {
+        copy::set_xattr(&path, &name, value.as_bytes())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_xattr(_: &Path, _: &str) -> io::Result<String> {
+    Ok("-".into())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn write_xattr(_: &Path, _: &str, _: &str) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+pub fn parse_mode(value: &str) -> Option<u32> {
+    match u32::from_str_radix(value, 8) {
+        Ok(mode) if mode <= 0o7777 => Some(mode),
+        _ => None,
+    }
+}
+
+/// Returns the name of the user `uid` or the number if it has no name.
+fn user_name(uid: libc::uid_t) -> String {
+    let entry = unsafe { libc::getpwuid(uid) };
+    if entry.is_null() {
+        return uid.to_string();
+    }
+    unsafe { CStr::from_ptr((*entry).pw_name) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Returns the name of the group `gid` or the number if it has no name.
+fn group_name(gid: libc::gid_t) -> String {
+    let entry = unsafe { libc::getgrgid(gid) };
+    if entry.is_null() {
+        return gid.to_string();
+    }
+    unsafe { CStr::from_ptr((*entry).gr_name) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn user_id(name: &str) -> Option<libc::uid_t> {
+    if let Ok(uid) = name.parse() {
+        return Some(uid);
+    }
+    let name = CString::new(name).ok()?;
+    let entry = unsafe { libc::getpwnam(name.as_ptr()) };
+    if entry.is_null() {
+        None
+    } else {
+        Some(unsafe { (*entry).pw_uid })
+    }
+}
+
+fn group_id(name: &str) -> Option<libc::gid_t> {
+    if let Ok(gid) = name.parse() {
+        return Some(gid);
+    }
+    let name = CString::new(name).ok()?;
+    let entry = unsafe { libc::getgrnam(name.as_ptr()) };
+    if entry.is_null() {
+        None
+    } else {
+        Some(unsafe { (*entry).gr_gid })
+    }
+}
+
+/// Parses `user:group`, where both are names or numbers.
+fn parse_owner(value: &str) -> Option<(libc::uid_t, libc::gid_t)> {
+    let mut parts = value.splitn(2, ':');
+    let user = user_id(parts.next()?)?;
+    let group = group_id(parts.next()?)?;
+    Some((user, group))
+}
+
+/// Formats `time` in seconds since the epoch as local time in ISO 8601
+/// format, such as `2021-06-01T14:03:09+02:00`.
+fn format_time(time: i64) -> String {
+    let time = time as libc::time_t;
+    let mut tm = MaybeUninit::<libc::tm>::zeroed();
+    let tm = unsafe {
+        libc::localtime_r(&time, tm.as_mut_ptr());
+        tm.assume_init()
+    };
+    let offset = tm.tm_gmtoff / 60;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}{:02}:{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec,
+        if offset < 0 { '-' } else { '+' },
+        offset.abs() / 60,
+        offset.abs() % 60
+    )
+}
+
+/// Returns the number of days from 1970-01-01 to the given date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn number(text: &str, range: std::ops::RangeInclusive<i64>) -> Option<i64> {
+    if !text.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    text.parse().ok().filter(|value| range.contains(value))
+}
+
+/// Parses a time in ISO 8601 format, `YYYY-MM-DDTHH:MM:SS` followed by `Z`
+/// or an offset such as `+02:00`, into seconds since the epoch. Times
+/// without an offset are local time. A space may separate the date and time.
+fn parse_time(value: &str) -> Option<i64> {
+    if value.len() < 19 || !value.is_char_boundary(19) {
+        return None;
+    }
+    let (date_time, zone) = value.split_at(19);
+    let bytes = date_time.as_bytes();
+    if bytes[4] != b'-'
+        || bytes[7] != b'-'
+        || (bytes[10] != b'T' && bytes[10] != b' ')
+        || bytes[13] != b':'
+        || bytes[16] != b':'
+    {
+        return None;
+    }
+    let year = number(&date_time[0..4], 0..=9999)?;
+    let month = number(&date_time[5..7], 1..=12)?;
+    let day = number(&date_time[8..10], 1..=31)?;
+    let hour = number(&date_time[11..13], 0..=23)?;
+    let minute = number(&date_time[14..16], 0..=59)?;
+    let second = number(&date_time[17..19], 0..=60)?;
+    let offset = match zone {
+        "" => None,
+        "Z" => Some(0),
+        _ => {
+            let sign = match zone.as_bytes()[0] {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            let zone = &zone[1..];
+            if zone.len() != 5 || zone.as_bytes()[2] != b':' {
+                return None;
+            }
+            let hours = number(&zone[..2], 0..=23)?;
+            let minutes = number(&zone[3..], 0..=59)?;
+            Some(sign * (hours * 3600 + minutes * 60))
+        }
+    };
+    match offset {
+        Some(offset) => {
+            let days = days_from_civil(year, month, day);
+            Some(days * 86400 + hour * 3600 + minute * 60 + second - offset)
+        }
+        None => {
+            let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+            tm.tm_year = (year - 1900) as libc::c_int;
+            tm.tm_mon = (month - 1) as libc::c_int;
+            tm.tm_mday = day as libc::c_int;
+            tm.tm_hour = hour as libc::c_int;
+            tm.tm_min = minute as libc::c_int;
+            tm.tm_sec = second as libc::c_int;
+            tm.tm_isdst = -1;
+            // time_t is narrower than i64 on some platforms.
+            #[allow(clippy::unnecessary_cast)]
+            match unsafe { libc::mktime(&mut tm) } {
+                -1 => None,
+                time => Some(time as i64),
+            }
+        }
+    }
+}
+
+/// Formats a buffer line with the attribute `values` before `name`.
+pub fn join(values: &[String], name: &[u8]) -> Vec<u8> {
+    let mut line = vec![];
+    for value in values {
+        line.extend_from_slice(value.as_bytes());
+        line.push(b'\t');
+    }
+    line.extend_from_slice(name);
+    line
+}
+
+/// Splits a buffer line into `count` attribute values and the name. Only the
+/// values are decoded, so names that are not valid UTF-8 are kept intact.
+pub fn split(line: &[u8], count: usize) -> Option<(Vec<String>, &[u8])> {
+    let mut fields = line.splitn(count + 1, |&byte| byte == b'\t');
+    let values = fields
+        .by_ref()
+        .take(count)
+        .map(|value| String::from_utf8_lossy(value).trim().to_owned())
+        .collect::<Vec<_>>();
+    match fields.next() {
+        Some(name) if values.len() == count => Some((values, name)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_keeps_names_that_are_not_utf8() {
+        let (values, name) = split(b"0644\tcaf\xe9.txt", 1).unwrap();
+        assert_eq!(values, ["0644"]);
+        assert_eq!(name, b"caf\xe9.txt");
+    }
+
+    #[test]
+    fn split_keeps_tabs_in_names() {
+        let (values, name) = split(b" alice \t0644\ta\tb", 2).unwrap();
+        assert_eq!(values, ["alice", "0644"]);
+        assert_eq!(name, b"a\tb");
+    }
+
+    #[test]
+    fn split_requires_all_values() {
+        assert!(split(b"0644", 1).is_none());
+    }
+
+    #[test]
+    fn parse_time_with_offsets() {
+        assert_eq!(parse_time("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(parse_time("2000-02-29 12:30:15Z"), Some(951_827_415));
+        assert_eq!(parse_time("2000-02-29T14:30:15+02:00"), Some(951_827_415));
+        assert_eq!(parse_time("2000-02-29T10:00:15-02:30"), Some(951_827_415));
+    }
+
+    #[test]
+    fn parse_time_rejects_malformed_input() {
+        assert_eq!(parse_time("2000-02-29T12:30:1xZ"), None);
+        assert_eq!(parse_time("2000-13-01T00:00:00Z"), None);
+        assert_eq!(parse_time("+000-01-01T00:00:00Z"), None);
+        assert_eq!(parse_time("2000-01-01T00:00:00+2:00"), None);
+        assert_eq!(parse_time("2000-01-01T00:00:00+0a:00"), None);
+        assert_eq!(parse_time("2000-01-01T00:00:00 UTC"), None);
+        assert_eq!(parse_time("2000-01-01"), None);
+    }
+}
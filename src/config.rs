@@ -0,0 +1,120 @@
+//! The configuration file, `$XDG_CONFIG_HOME/bulkrename/config`.
+//!
+//! The file consists of `key = value` lines. Empty lines and lines starting
+//! with `#` are ignored.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::glob::Pattern;
+use crate::rules::Rule;
+use crate::{Context, Error, Operation};
+
+/// What to do with input files matching a protected pattern.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ProtectAction {
+    /// Refuse to rename anything.
+    Refuse,
+    /// Leave the files out of the list.
+    Skip,
+}
+
+#[derive(Clone)]
+pub struct Config {
+    /// Patterns of files that are never renamed.
+    pub protect: Vec<Pattern>,
+    pub protect_action: ProtectAction,
+    /// Rules giving new names to files, for organizing directories.
+    pub rules: Vec<Rule>,
+    /// Templates of new names by the preset name, for `--preset`.
+    pub presets: HashMap<String, String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            protect: vec![],
+            protect_action: ProtectAction::Refuse,
+            rules: vec![],
+            presets: HashMap::new(),
+        }
+    }
+}
+
+fn dir() -> Option<PathBuf> {
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => env::var_os("HOME").map(|home| Path::new(&home).join(".config")),
+    }
+    .map(|dir| dir.join("bulkrename"))
+}
+
+fn path() -> Option<PathBuf> {
+    dir().map(|dir| dir.join("config"))
+}
+
+/// Returns the directory plugins are looked up in.
+pub fn plugins_dir() -> Option<PathBuf> {
+    dir().map(|dir| dir.join("plugins"))
+}
+
+/// Loads the configuration file, if there is one.
+pub fn load() -> Result<Config, Error> {
+    let path = match path() {
+        Some(path) => path,
+        None => return Ok(Config::default()),
+    };
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(err) => return Err(err).with_path(Operation::Read, &path),
+    };
+    parse(&content).map_err(|(line, message)| Error::InvalidConfig(path, line, message))
+}
+
+fn parse(content: &str) -> Result<Config, (usize, String)> {
+    let mut config = Config::default();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |message: String| (index + 1, message);
+        let (key, value) = match line.find('=') {
+            Some(index) => (line[..index].trim(), line[index + 1..].trim()),
+            None => return Err(error("expected 'key = value'".into())),
+        };
+        match key {
+            "protect" => config.protect.push(Pattern::new(value)),
+            "protect-action" => {
+                config.protect_action = match value {
+                    "refuse" => ProtectAction::Refuse,
+                    "skip" => ProtectAction::Skip,
+                    _ => return Err(error(format!("invalid protect-action '{}'", value))),
+                }
+            }
+            "rule" => match value.find("->") {
+                Some(index) if !value[index + 2..].trim().is_empty() => config.rules.push(Rule {
+                    pattern: Pattern::new(value[..index].trim()),
+                    template: value[index + 2..].trim().into(),
+                }),
+                _ => return Err(error("expected 'rule = PATTERN -> TEMPLATE'".into())),
+            },
+            _ => match key.strip_prefix("preset") {
+                Some(name) if name.starts_with(char::is_whitespace) && !value.is_empty() => {
+                    let template = value
+                        .strip_prefix('"')
+                        .and_then(|value| value.strip_suffix('"'))
+                        .unwrap_or(value);
+                    config.presets.insert(name.trim().into(), template.into());
+                }
+                Some(_) => return Err(error("expected 'preset NAME = TEMPLATE'".into())),
+                None => return Err(error(format!("unknown key '{}'", key))),
+            },
+        }
+    }
+    Ok(config)
+}
@@ -0,0 +1,121 @@
+//! Interactive resolution of conflicts with existing destination files.
+
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::quote::Quote;
+
+pub enum Resolution {
+    Overwrite,
+    Skip,
+    /// Rename to another name in the directory of the conflicting
+    /// destination.
+    Rename(PathBuf),
+}
+
+/// Asks the user how to deal with existing destinations. Prompts are read
+/// from the controlling terminal so that they work even when the file list
+/// was piped in.
+pub struct Resolver {
+    tty: Option<io::BufReader<fs::File>>,
+    /// Answer to all questions given on the command line, if any.
+    answer: Option<bool>,
+    /// Answer given with "overwrite all" (`true`) or "skip all" (`false`).
+    remembered: Option<bool>,
+}
+
+impl Resolver {
+    /// Returns a resolver that answers every question with `answer` instead
+    /// of prompting, if given.
+    pub fn new(answer: Option<bool>) -> Self {
+        Resolver {
+            tty: None,
+            answer,
+            remembered: answer,
+        }
+    }
+
+    fn tty(&mut self) -> io::Result<&mut io::BufReader<fs::File>> {
+        if self.tty.is_none() {
+            let tty = fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open("/dev/tty")
+                .map_err(|err| {
+                    io::Error::new(
+                        err.kind(),
+                        "no terminal available for prompts, use --yes or --batch",
+                    )
+                })?;
+            self.tty = Some(io::BufReader::new(tty));
+        }
+        Ok(self.tty.as_mut().unwrap())
+    }
+
+    fn ask(&mut self, question: &str) -> io::Result<String> {
+        let tty = self.tty()?;
+        tty.get_mut().write_all(question.as_bytes())?;
+        let mut answer = String::new();
+        if tty.read_line(&mut answer)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(answer.trim_end_matches('\n').to_owned())
+    }
+
+    /// Asks a yes or no question.
+    pub fn confirm(&mut self, question: &str) -> io::Result<bool> {
+        if let Some(answer) = self.answer {
+            return Ok(answer);
+        }
+        let question = format!("{} [y/n] ", question);
+        loop {
+            match self.ask(&question)?.trim() {
+                "y" => return Ok(true),
+                "n" => return Ok(false),
+                _ => {}
+            }
+        }
+    }
+
+    pub fn resolve(&mut self, dest: &Path) -> io::Result<Resolution> {
+        match self.remembered {
+            Some(true) => return Ok(Resolution::Overwrite),
+            Some(false) => return Ok(Resolution::Skip),
+            None => {}
+        }
+        let question = format!(
+            "'{}' already exists: [o]verwrite, [s]kip, [r]ename, overwrite [a]ll, s[k]ip all? ",
+            dest.quoted()
+        );
+        loop {
+            match self.ask(&question)?.trim() {
+                "o" => return Ok(Resolution::Overwrite),
+                "s" => return Ok(Resolution::Skip),
+                "r" => loop {
+                    // Other directories may be outside of what the sandbox
+                    // allows, so only names in the same one are taken.
+                    let name = self.ask("new name: ")?;
+                    if name.is_empty() {
+                        break;
+                    } else if name.contains('/') || name == "." || name == ".." {
+                        self.tty()?.get_mut().write_all(
+                            b"the new name must be a file name in the same directory\n",
+                        )?;
+                    } else {
+                        return Ok(Resolution::Rename(dest.with_file_name(name)));
+                    }
+                },
+                "a" => {
+                    self.remembered = Some(true);
+                    return Ok(Resolution::Overwrite);
+                }
+                "k" => {
+                    self.remembered = Some(false);
+                    return Ok(Resolution::Skip);
+                }
+                _ => {}
+            }
+        }
+    }
+}
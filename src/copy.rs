@@ -0,0 +1,755 @@
+//! Moving files between file systems by copying and deleting them, used when
+//! a rename fails with `EXDEV`.
+
+use std::collections::HashMap;
+use std::ffi::{CString, OsStr};
+use std::fs;
+use std::io::{self, Read};
+use std::os::raw;
+use std::os::unix::ffi::OsStrExt;
+#[cfg(not(target_os = "macos"))]
+use std::os::unix::fs::FileExt;
+#[cfg(target_os = "linux")]
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::fs::{symlink, MetadataExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use crate::blake3;
+use crate::quote::Quote;
+use crate::{parent_dir, rename};
+
+/// How the security context of copied files is determined.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Context {
+    /// Copy the context of the source file.
+    Preserve,
+    /// Use the context that new files in the destination directory receive.
+    Default,
+}
+
+/// Attributes retained when copying, as with `cp --preserve`.
+#[derive(Clone, Copy)]
+pub struct Preserve {
+    pub mode: bool,
+    pub ownership: bool,
+    pub timestamps: bool,
+    pub xattr: bool,
+    /// Hard links between copied files.
+    pub links: bool,
+}
+
+impl Preserve {
+    pub const NONE: Preserve = Preserve {
+        mode: false,
+        ownership: false,
+        timestamps: false,
+        xattr: false,
+        links: false,
+    };
+
+    /// Parses a comma separated list of attribute names.
+    pub fn parse(list: &str) -> Option<Self> {
+        let mut preserve = Preserve::NONE;
+        for attribute in list.split(',') {
+            match attribute {
+                "mode" => preserve.mode = true,
+                "ownership" => preserve.ownership = true,
+                "timestamps" => preserve.timestamps = true,
+                "xattr" => preserve.xattr = true,
+                "links" => preserve.links = true,
+                "all" => {
+                    preserve = Preserve {
+                        mode: true,
+                        ownership: true,
+                        timestamps: true,
+                        xattr: true,
+                        links: true,
+                    }
+                }
+                _ => return None,
+            }
+        }
+        Some(preserve)
+    }
+}
+
+#[derive(Clone)]
+pub struct Options {
+    pub context: Context,
+    pub preserve: Preserve,
+    /// Compare checksums of the copies and the originals before removing
+    /// the originals.
+    pub verify: bool,
+    /// Flush copied files to disk before removing the originals.
+    pub sync: bool,
+}
+
+/// Makes a file share the data of another on file systems such as Btrfs and
+/// XFS, from <linux/fs.h>.
+#[cfg(target_os = "linux")]
+const FICLONE: libc::c_ulong = 0x40049409;
+
+#[cfg(target_os = "linux")]
+const SELINUX_XATTR: &[u8] = b"security.selinux";
+
+/// Returns the path that `dest` is assembled at before being moved into
+/// place, so that a partial copy never appears under its final name.
+pub fn staging_path(dest: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(dest.file_name().unwrap_or_default());
+    name.push(".bulkrename");
+    dest.with_file_name(name)
+}
+
+fn remove_tree(path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+pub fn c_path(path: &Path) -> io::Result<CString> {
+    Ok(CString::new(path.as_os_str().as_bytes())?)
+}
+
+#[cfg(target_os = "linux")]
+fn list_xattrs(path: &CString) -> io::Result<Vec<CString>> {
+    let size = unsafe { libc::llistxattr(path.as_ptr(), std::ptr::null_mut(), 0) };
+    if size < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut buffer = vec![0u8; size as usize];
+    let size =
+        unsafe { libc::llistxattr(path.as_ptr(), buffer.as_mut_ptr() as *mut _, buffer.len()) };
+    if size < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    buffer.truncate(size as usize);
+    Ok(buffer
+        .split(|&byte| byte == 0)
+        .filter(|name| !name.is_empty())
+        .filter_map(|name| CString::new(name).ok())
+        .collect())
+}
+
+#[cfg(target_os = "linux")]
+pub fn get_xattr(path: &CString, name: &CString) -> io::Result<Vec<u8>> {
+    let size = unsafe { libc::lgetxattr(path.as_ptr(), name.as_ptr(), std::ptr::null_mut(), 0) };
+    if size < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut value = vec![0u8; size as usize];
+    let size = unsafe {
+        libc::lgetxattr(
+            path.as_ptr(),
+            name.as_ptr(),
+            value.as_mut_ptr() as *mut _,
+            value.len(),
+        )
+    };
+    if size < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    value.truncate(size as usize);
+    Ok(value)
+}
+
+#[cfg(target_os = "linux")]
+pub fn set_xattr(path: &CString, name: &CString, value: &[u8]) -> io::Result<()> {
+    if unsafe {
+        libc::lsetxattr(
+            path.as_ptr(),
+            name.as_ptr(),
+            value.as_ptr() as *const _,
+            value.len(),
+            0,
+        )
+    } == -1
+    {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+pub fn remove_xattr(path: &CString, name: &CString) -> io::Result<()> {
+    if unsafe { libc::lremovexattr(path.as_ptr(), name.as_ptr()) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Extended attributes whose loss is reported: user attributes and POSIX
+/// ACLs, which the kernel exposes as attributes in the `system` namespace.
+#[cfg(target_os = "linux")]
+const REPORTED_XATTRS: &[&[u8]] = &[
+    b"user.",
+    b"system.posix_acl_access",
+    b"system.posix_acl_default",
+    SELINUX_XATTR,
+];
+
+/// Copies extended attributes, including ACLs, from `source` to `dest`, or
+/// to `file` if the copy is open. Attributes in privileged namespaces, such
+/// as file capabilities, are copied only where permitted. The SELinux context
+/// is copied only if requested in `options`.
+#[cfg(target_os = "linux")]
+fn copy_xattrs(
+    source: &Path,
+    dest: &Path,
+    file: Option<&fs::File>,
+    options: &Options,
+) -> io::Result<()> {
+    let source_path = c_path(source)?;
+    let dest_path = c_path(dest)?;
+    let names = match list_xattrs(&source_path) {
+        Ok(names) => names,
+        Err(err) if err.raw_os_error() == Some(libc::ENOTSUP) => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    for name in names {
+        if options.context == Context::Default && name.as_bytes() == SELINUX_XATTR {
+            continue;
+        }
+        let value = get_xattr(&source_path, &name)?;
+        let result = match file {
+            Some(file) => check(unsafe {
+                libc::fsetxattr(
+                    file.as_raw_fd(),
+                    name.as_ptr(),
+                    value.as_ptr() as *const _,
+                    value.len(),
+                    0,
+                )
+            }),
+            None => set_xattr(&dest_path, &name, &value),
+        };
+        if let Err(err) = result {
+            let name_bytes = name.as_bytes();
+            if REPORTED_XATTRS
+                .iter()
+                .any(|prefix| name_bytes.starts_with(prefix))
+            {
+                eprintln!(
+                    "bulkrename: warning: could not copy extended attribute {} to {}: {}",
+                    OsStr::from_bytes(name.as_bytes()).quoted(),
+                    dest.quoted(),
+                    err
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn copy_xattrs(_: &Path, _: &Path, _: Option<&fs::File>, _: &Options) -> io::Result<()> {
+    Ok(())
+}
+
+fn warn(what: &str, path: &Path, err: io::Error) {
+    eprintln!(
+        "bulkrename: warning: could not preserve {} of {}: {}",
+        what,
+        path.quoted(),
+        err
+    );
+}
+
+fn check(result: raw::c_int) -> io::Result<()> {
+    if result == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns the permissions newly created files receive given the umask.
+fn default_mode(is_dir: bool) -> u32 {
+    let umask = unsafe {
+        let umask = libc::umask(0);
+        libc::umask(umask);
+        umask
+    };
+    let mode = if is_dir { 0o777 } else { 0o666 };
+    mode & !(umask as u32)
+}
+
+/// Copies the bytes from `offset` to `end` with copy_file_range(2), which
+/// copies within the kernel, and returns the offset up to which it copied.
+/// Stops early if the file systems do not support it.
+#[cfg(target_os = "linux")]
+fn copy_in_kernel(
+    source: &fs::File,
+    dest: &fs::File,
+    mut offset: u64,
+    end: u64,
+) -> io::Result<u64> {
+    while offset < end {
+        let mut source_offset = offset as libc::loff_t;
+        let mut dest_offset = offset as libc::loff_t;
+        let copied = unsafe {
+            libc::syscall(
+                libc::SYS_copy_file_range,
+                source.as_raw_fd(),
+                &mut source_offset,
+                dest.as_raw_fd(),
+                &mut dest_offset,
+                (end - offset) as usize,
+                0 as raw::c_uint,
+            )
+        };
+        match copied {
+            -1 => {
+                let err = io::Error::last_os_error();
+                match err.raw_os_error() {
+                    Some(libc::EINTR) => continue,
+                    Some(libc::EOPNOTSUPP)
+                    | Some(libc::ENOSYS)
+                    | Some(libc::EXDEV)
+                    | Some(libc::EINVAL) => break,
+                    _ => return Err(err),
+                }
+            }
+            // The source is shorter than expected.
+            0 => break,
+            copied => offset += copied as u64,
+        }
+    }
+    Ok(offset)
+}
+
+/// Copies `len` bytes at `offset` from `source` to the same offset in
+/// `dest`.
+#[cfg(not(target_os = "macos"))]
+fn copy_range(source: &fs::File, dest: &fs::File, mut offset: u64, len: u64) -> io::Result<()> {
+    let end = offset + len;
+    #[cfg(target_os = "linux")]
+    {
+        offset = copy_in_kernel(source, dest, offset, end)?;
+    }
+    let mut buffer = vec![0; 128 * 1024];
+    while offset < end {
+        let want = buffer.len().min((end - offset) as usize);
+        let n = match source.read_at(&mut buffer[..want], offset) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        dest.write_all_at(&buffer[..n], offset)?;
+        offset += n as u64;
+    }
+    Ok(())
+}
+
+/// Returns the regions of `file` that contain data, skipping holes.
+/// Returns `None` if the file system cannot report holes.
+#[cfg(target_os = "linux")]
+fn data_regions(file: &fs::File, len: u64) -> io::Result<Option<Vec<(u64, u64)>>> {
+    let fd = file.as_raw_fd();
+    let mut regions = vec![];
+    let mut offset = 0;
+    while offset < len {
+        let data = unsafe { libc::lseek(fd, offset as libc::off_t, libc::SEEK_DATA) };
+        if data == -1 {
+            let err = io::Error::last_os_error();
+            return match err.raw_os_error() {
+                // No data after offset.
+                Some(libc::ENXIO) => Ok(Some(regions)),
+                Some(libc::EINVAL) | Some(libc::EOPNOTSUPP) => Ok(None),
+                _ => Err(err),
+            };
+        }
+        let hole = unsafe { libc::lseek(fd, data, libc::SEEK_HOLE) };
+        if hole == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        regions.push((data as u64, (hole - data) as u64));
+        offset = hole as u64;
+    }
+    Ok(Some(regions))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn data_regions(_: &fs::File, _: u64) -> io::Result<Option<Vec<(u64, u64)>>> {
+    Ok(None)
+}
+
+/// Copies the contents of a regular file, preserving holes in sparse files.
+/// The copy shares the data of the original where the file system allows,
+/// as between mounts of the same Btrfs file system. A partial copy is
+/// removed.
+#[cfg(not(target_os = "macos"))]
+fn copy_file(source: &Path, dest: &Path, sync: bool) -> io::Result<()> {
+    let source = fs::File::open(source)?;
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(dest)?;
+    copy_contents(&source, &file, sync).inspect_err(|_| {
+        let _ = fs::remove_file(dest);
+    })
+}
+
+/// Copies the contents of the open regular file `source` to `dest`.
+#[cfg(not(target_os = "macos"))]
+fn copy_contents(source: &fs::File, dest: &fs::File, sync: bool) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        if unsafe { libc::ioctl(dest.as_raw_fd(), FICLONE, source.as_raw_fd()) } == 0 {
+            if sync {
+                dest.sync_all()?;
+            }
+            return Ok(());
+        }
+    }
+    let len = source.metadata()?.len();
+    let regions = data_regions(source, len)?.unwrap_or_else(|| vec![(0, len)]);
+    for (offset, region_len) in regions {
+        copy_range(source, dest, offset, region_len)?;
+    }
+    // Leaves a trailing hole where the source has one.
+    dest.set_len(len)?;
+    if sync {
+        dest.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Copies a regular file with copyfile(3), which clones it on file systems
+/// that support it and keeps what other copies lose, such as the creation
+/// date, file flags, Finder information and resource forks.
+#[cfg(target_os = "macos")]
+fn copy_file(source: &Path, dest: &Path, sync: bool) -> io::Result<()> {
+    // From <copyfile.h>.
+    const COPYFILE_ALL: u32 = 0xf;
+    const COPYFILE_EXCL: u32 = 1 << 17;
+    const COPYFILE_NOFOLLOW_SRC: u32 = 1 << 18;
+    const COPYFILE_CLONE: u32 = 1 << 24;
+    extern "C" {
+        fn copyfile(
+            from: *const raw::c_char,
+            to: *const raw::c_char,
+            state: *mut libc::c_void,
+            flags: u32,
+        ) -> raw::c_int;
+    }
+    let (source, dest_path) = (c_path(source)?, c_path(dest)?);
+    check(unsafe {
+        copyfile(
+            source.as_ptr(),
+            dest_path.as_ptr(),
+            std::ptr::null_mut(),
+            COPYFILE_ALL | COPYFILE_EXCL | COPYFILE_NOFOLLOW_SRC | COPYFILE_CLONE,
+        )
+    })
+    .and_then(|_| match sync {
+        true => fs::File::open(dest)?.sync_all(),
+        false => Ok(()),
+    })
+    .inspect_err(|err| {
+        if err.kind() != io::ErrorKind::AlreadyExists {
+            let _ = fs::remove_file(dest);
+        }
+    })
+}
+
+struct Copier<'a> {
+    options: &'a Options,
+    /// Copies of files with multiple hard links, by source identity.
+    links: HashMap<(u64, u64), PathBuf>,
+    /// The staging path of the copy and the destination it is moved to.
+    staging: &'a Path,
+    dest: &'a Path,
+    /// Whether the staging path was created by this copy, rather than being
+    /// left by something else, and may be removed if the copy fails.
+    created: bool,
+}
+
+impl Copier<'_> {
+    /// Returns the final path of `path` in the copy, for messages.
+    fn shown(&self, path: &Path) -> PathBuf {
+        match path.strip_prefix(self.staging) {
+            Ok(rest) if rest.as_os_str().is_empty() => self.dest.to_owned(),
+            Ok(rest) => self.dest.join(rest),
+            Err(_) => path.to_owned(),
+        }
+    }
+
+    /// Gives `dest`, or `file` if the copy is open, the attributes of
+    /// `source` selected in the options.
+    fn copy_attributes(
+        &self,
+        source: &Path,
+        dest: &Path,
+        file: Option<&fs::File>,
+        metadata: &fs::Metadata,
+    ) -> io::Result<()> {
+        let preserve = &self.options.preserve;
+        let is_symlink = metadata.file_type().is_symlink();
+        let dest_path = c_path(dest)?;
+        if preserve.ownership {
+            // Changing the owner clears set-user-ID bits, so it comes first.
+            let (uid, gid) = (metadata.uid(), metadata.gid());
+            let result = match file {
+                Some(file) => check(unsafe { libc::fchown(file.as_raw_fd(), uid, gid) }),
+                None => check(unsafe { libc::lchown(dest_path.as_ptr(), uid, gid) }),
+            };
+            if let Err(err) = result {
+                if err.raw_os_error() == Some(libc::EPERM) && unsafe { libc::geteuid() } != 0 {
+                    warn(
+                        "ownership",
+                        &self.shown(dest),
+                        io::Error::other("only root can give files to other users"),
+                    );
+                } else {
+                    warn("ownership", &self.shown(dest), err);
+                }
+            }
+        }
+        if !is_symlink {
+            let mode = if preserve.mode {
+                metadata.mode() & 0o7777
+            } else {
+                default_mode(metadata.is_dir())
+            };
+            let permissions = fs::Permissions::from_mode(mode);
+            match file {
+                Some(file) => file.set_permissions(permissions)?,
+                None => fs::set_permissions(dest, permissions)?,
+            }
+        }
+        if preserve.xattr {
+            copy_xattrs(source, dest, file, self.options)?;
+        }
+        if preserve.timestamps {
+            let times = [
+                libc::timespec {
+                    tv_sec: metadata.atime() as libc::time_t,
+                    tv_nsec: metadata.atime_nsec() as _,
+                },
+                libc::timespec {
+                    tv_sec: metadata.mtime() as libc::time_t,
+                    tv_nsec: metadata.mtime_nsec() as _,
+                },
+            ];
+            if let Err(err) = check(unsafe {
+                match file {
+                    Some(file) => libc::futimens(file.as_raw_fd(), times.as_ptr()),
+                    None => libc::utimensat(
+                        libc::AT_FDCWD,
+                        dest_path.as_ptr(),
+                        times.as_ptr(),
+                        libc::AT_SYMLINK_NOFOLLOW,
+                    ),
+                }
+            }) {
+                warn("timestamps", &self.shown(dest), err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Notes that `dest` was created, for cleaning up after a failed copy.
+    fn created(&mut self, dest: &Path) {
+        if dest == self.staging {
+            self.created = true;
+        }
+    }
+
+    fn copy_tree(&mut self, source: &Path, dest: &Path) -> io::Result<()> {
+        let metadata = fs::symlink_metadata(source)?;
+        let file_type = metadata.file_type();
+        if file_type.is_symlink() {
+            symlink(fs::read_link(source)?, dest)?;
+            self.created(dest);
+        } else if file_type.is_dir() {
+            fs::create_dir(dest)?;
+            self.created(dest);
+            for entry in fs::read_dir(source)? {
+                let entry = entry?;
+                self.copy_tree(&entry.path(), &dest.join(entry.file_name()))?;
+            }
+            if self.options.sync {
+                fs::File::open(dest)?.sync_all()?;
+            }
+        } else if file_type.is_file() {
+            if self.options.preserve.links && metadata.nlink() > 1 {
+                let identity = (metadata.dev(), metadata.ino());
+                if let Some(copy) = self.links.get(&identity) {
+                    return fs::hard_link(copy, dest);
+                }
+                self.links.insert(identity, dest.to_owned());
+            }
+            copy_file(source, dest, self.options.sync)?;
+            self.created(dest);
+        } else {
+            return Err(io::Error::other(format!(
+                "cannot copy special file {}",
+                source.quoted()
+            )));
+        }
+        self.copy_attributes(source, dest, None, &metadata)
+    }
+}
+
+fn checksum(path: &Path) -> io::Result<[u8; 32]> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = blake3::Hasher::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        match file.read(&mut buffer) {
+            Ok(0) => return Ok(hasher.finalize()),
+            Ok(n) => hasher.update(&buffer[..n]),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Returns true if `a` and `b` are regular files with the same contents.
+pub fn same_contents(a: &Path, b: &Path) -> io::Result<bool> {
+    let (a_metadata, b_metadata) = (fs::symlink_metadata(a)?, fs::symlink_metadata(b)?);
+    if !a_metadata.is_file() || !b_metadata.is_file() || a_metadata.len() != b_metadata.len() {
+        return Ok(false);
+    }
+    let (a_sum, b_sum) = thread::scope(|scope| {
+        let a_sum = scope.spawn(|| checksum(a));
+        let b_sum = checksum(b);
+        (a_sum.join().unwrap(), b_sum)
+    });
+    Ok(a_sum? == b_sum?)
+}
+
+/// Checks that the regular files in the tree at `copy` have the same
+/// contents as those in `source`. Both sides are read concurrently.
+fn verify_tree(source: &Path, copy: &Path) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(source)?;
+    if metadata.is_dir() {
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            verify_tree(&entry.path(), &copy.join(entry.file_name()))?;
+        }
+    } else if metadata.is_file() {
+        let (source_sum, copy_sum) = thread::scope(|scope| {
+            let source_sum = scope.spawn(|| checksum(source));
+            let copy_sum = checksum(copy);
+            (source_sum.join().unwrap(), copy_sum)
+        });
+        if source_sum? != copy_sum? {
+            return Err(io::Error::other(format!(
+                "copy of {} does not match the original",
+                source.quoted()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Copies the regular file `source` to an unnamed file in the directory of
+/// `dest`, which is linked into place only once it is complete, so that an
+/// interrupted copy leaves nothing behind. Returns false without copying if
+/// the file system does not support unnamed files.
+#[cfg(target_os = "linux")]
+fn copy_unnamed(source: &Path, dest: &Path, replace: bool, options: &Options) -> io::Result<bool> {
+    let file = match fs::OpenOptions::new()
+        .write(true)
+        .mode(0o600)
+        .custom_flags(libc::O_TMPFILE)
+        .open(parent_dir(dest))
+    {
+        Ok(file) => file,
+        Err(err)
+            if matches!(
+                err.raw_os_error(),
+                Some(libc::EOPNOTSUPP) | Some(libc::EISDIR) | Some(libc::EINVAL)
+            ) =>
+        {
+            return Ok(false)
+        }
+        Err(err) => return Err(err),
+    };
+    let copier = Copier {
+        options,
+        links: HashMap::new(),
+        staging: dest,
+        dest,
+        created: false,
+    };
+    let metadata = fs::symlink_metadata(source)?;
+    copy_contents(&fs::File::open(source)?, &file, options.sync)?;
+    copier.copy_attributes(source, dest, Some(&file), &metadata)?;
+    let unnamed = PathBuf::from(format!("/proc/self/fd/{}", file.as_raw_fd()));
+    if options.verify {
+        verify_tree(source, &unnamed)?;
+    }
+    let link = |path: &Path| {
+        let (unnamed, path) = (c_path(&unnamed)?, c_path(path)?);
+        check(unsafe {
+            libc::linkat(
+                libc::AT_FDCWD,
+                unnamed.as_ptr(),
+                libc::AT_FDCWD,
+                path.as_ptr(),
+                libc::AT_SYMLINK_FOLLOW,
+            )
+        })
+    };
+    if replace {
+        let staging = staging_path(dest);
+        link(&staging)?;
+        if let Err(err) = rename(&staging, dest, true) {
+            let _ = fs::remove_file(&staging);
+            return Err(err);
+        }
+    } else {
+        link(dest)?;
+    }
+    Ok(true)
+}
+
+/// Moves `source` to `dest` on a different file system.
+pub fn move_across(source: &Path, dest: &Path, replace: bool, options: &Options) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        if fs::symlink_metadata(source)?.is_file() && copy_unnamed(source, dest, replace, options)?
+        {
+            if options.sync {
+                fs::File::open(parent_dir(dest))?.sync_all()?;
+            }
+            return fs::remove_file(source);
+        }
+    }
+    let staging = staging_path(dest);
+    let mut copier = Copier {
+        options,
+        links: HashMap::new(),
+        staging: &staging,
+        dest,
+        created: false,
+    };
+    if let Err(err) = copier
+        .copy_tree(source, &staging)
+        .and_then(|_| {
+            if options.verify {
+                verify_tree(source, &staging)
+            } else {
+                Ok(())
+            }
+        })
+        .and_then(|_| rename(&staging, dest, replace))
+    {
+        if copier.created {
+            let _ = remove_tree(&staging);
+        }
+        return Err(err);
+    }
+    if options.sync {
+        fs::File::open(parent_dir(dest))?.sync_all()?;
+    }
+    remove_tree(source)
+}
@@ -0,0 +1,262 @@
+//! Reading rename plans from CSV and TSV files, as exported by spreadsheets.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use crate::{Context, Error, Operation};
+
+/// Options given for a single row in the optional third column.
+#[derive(Clone, Copy, Default)]
+pub struct Flags {
+    /// Replace an existing destination.
+    pub replace: bool,
+    /// Replace an existing destination, keeping a backup of it.
+    pub backup: bool,
+}
+
+impl Flags {
+    fn parse(field: &[u8], line: usize) -> Result<Self, Error> {
+        let mut flags = Flags::default();
+        let words = field
+            .split(|&byte| byte == b' ' || byte == b';' || byte == b'|')
+            .filter(|word| !word.is_empty());
+        for word in words {
+            match word {
+                b"replace" => flags.replace = true,
+                b"backup" => flags.backup = true,
+                _ => {
+                    return Err(Error::InvalidPlan(
+                        line,
+                        format!("unknown flag '{}'", String::from_utf8_lossy(word)),
+                    ))
+                }
+            }
+        }
+        Ok(flags)
+    }
+}
+
+/// A plan read from a file.
+pub struct Plan {
+    pub sources: Vec<PathBuf>,
+    pub destinations: Vec<PathBuf>,
+    pub flags: Vec<Flags>,
+    /// The line each row starts on.
+    pub lines: Vec<usize>,
+}
+
+/// The fields of a record and the line it starts on.
+type Record = (usize, Vec<Vec<u8>>);
+
+/// Splits `content` into records of fields separated by `delimiter`. Fields
+/// may be quoted with double quotes, in which case they can contain the
+/// delimiter, line breaks and doubled quotes. Returns the records together
+/// with the line numbers they start on.
+fn records(content: &[u8], delimiter: u8) -> Result<Vec<Record>, Error> {
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut field = vec![];
+    let mut line = 1;
+    let mut start = 1;
+    let mut quoted = false;
+    let mut bytes = content.iter().copied().peekable();
+    while let Some(byte) = bytes.next() {
+        if quoted {
+            match byte {
+                b'"' if bytes.peek() == Some(&b'"') => {
+                    bytes.next();
+                    field.push(b'"');
+                }
+                b'"' => quoted = false,
+                b'\n' => {
+                    line += 1;
+                    field.push(byte);
+                }
+                _ => field.push(byte),
+            }
+            continue;
+        }
+        match byte {
+            b'"' if field.is_empty() => quoted = true,
+            b'\r' if bytes.peek() == Some(&b'\n') => {}
+            b'\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push((start, std::mem::take(&mut record)));
+                line += 1;
+                start = line;
+            }
+            _ if byte == delimiter => record.push(std::mem::take(&mut field)),
+            _ => field.push(byte),
+        }
+    }
+    if quoted {
+        return Err(Error::InvalidPlan(
+            start,
+            "unterminated quoted field".into(),
+        ));
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push((start, record));
+    }
+    Ok(records)
+}
+
+fn is_header(record: &[Vec<u8>]) -> bool {
+    match record {
+        [source, destination, ..] => {
+            source.eq_ignore_ascii_case(b"source")
+                && destination.eq_ignore_ascii_case(b"destination")
+        }
+        _ => false,
+    }
+}
+
+/// Reads a plan from `path`. Fields are separated by tabs if the file name
+/// ends with `.tsv` and by commas otherwise. The first row is skipped if it
+/// is a header naming the `source` and `destination` columns. Columns after
+/// the third are ignored.
+pub fn read(path: &Path) -> Result<Plan, Error> {
+    let delimiter = match path.extension() {
+        Some(extension) if extension.eq_ignore_ascii_case("tsv") => b'\t',
+        _ => b',',
+    };
+    let content = fs::read(path).with_path(Operation::Read, path)?;
+    let mut plan = Plan {
+        sources: vec![],
+        destinations: vec![],
+        flags: vec![],
+        lines: vec![],
+    };
+    for (index, (line, record)) in records(&content, delimiter)?.into_iter().enumerate() {
+        if index == 0 && is_header(&record) {
+            continue;
+        }
+        if record.iter().all(Vec::is_empty) {
+            continue;
+        }
+        let (source, destination) = match record.as_slice() {
+            [source, destination, ..] if !source.is_empty() && !destination.is_empty() => {
+                (source, destination)
+            }
+            _ => {
+                return Err(Error::InvalidPlan(
+                    line,
+                    "expected a source and a destination".into(),
+                ))
+            }
+        };
+        let flags = match record.get(2) {
+            Some(field) => Flags::parse(field, line)?,
+            None => Flags::default(),
+        };
+        plan.sources.push(OsStr::from_bytes(source).into());
+        plan.destinations
+            .push(OsStr::from_bytes(destination).into());
+        plan.flags.push(flags);
+        plan.lines.push(line);
+    }
+    Ok(plan)
+}
+
+pub fn write_field<W>(writer: &mut W, field: &[u8]) -> io::Result<()>
+where
+    W: Write,
+{
+    if !field.iter().any(|byte| b",\"\r\n".contains(byte)) {
+        return writer.write_all(field);
+    }
+    writer.write_all(b"\"")?;
+    for &byte in field {
+        if byte == b'"' {
+            writer.write_all(b"\"")?;
+        }
+        writer.write_all(&[byte])?;
+    }
+    writer.write_all(b"\"")
+}
+
+/// Writes `rows` of sources, destinations and how they are moved as a CSV
+/// plan with a header, which can be read back with `read`. The flags column
+/// is left empty.
+pub fn write<'a, W, I>(writer: &mut W, rows: I) -> io::Result<()>
+where
+    W: Write,
+    I: IntoIterator<Item = (&'a Path, &'a Path, &'a str)>,
+{
+    writer.write_all(b"source,destination,flags,strategy\n")?;
+    for (source, destination, strategy) in rows {
+        write_field(writer, source.as_os_str().as_bytes())?;
+        writer.write_all(b",")?;
+        write_field(writer, destination.as_os_str().as_bytes())?;
+        writer.write_all(b",,")?;
+        write_field(writer, strategy.as_bytes())?;
+        writer.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(line: usize, fields: &[&str]) -> Record {
+        let fields = fields.iter().map(|field| field.as_bytes().to_vec());
+        (line, fields.collect())
+    }
+
+    #[test]
+    fn quoted_fields() {
+        assert_eq!(
+            records(b"\"a,b\",\"say \"\"hi\"\"\"\n\"two\nlines\",c\nd,e\n", b',').unwrap(),
+            [
+                record(1, &["a,b", "say \"hi\""]),
+                record(2, &["two\nlines", "c"]),
+                record(4, &["d", "e"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn crlf_line_endings() {
+        assert_eq!(
+            records(b"a,b\r\n\"c\r\nd\",e\r\n", b',').unwrap(),
+            [record(1, &["a", "b"]), record(2, &["c\r\nd", "e"])]
+        );
+    }
+
+    #[test]
+    fn missing_final_newline() {
+        assert_eq!(
+            records(b"a,b\nc,d", b',').unwrap(),
+            [record(1, &["a", "b"]), record(2, &["c", "d"])]
+        );
+        assert_eq!(records(b"a,", b',').unwrap(), [record(1, &["a", ""])]);
+    }
+
+    #[test]
+    fn tab_delimiter() {
+        assert_eq!(
+            records(b"a,b\tc\n", b'\t').unwrap(),
+            [record(1, &["a,b", "c"])]
+        );
+    }
+
+    #[test]
+    fn unterminated_quote() {
+        assert!(records(b"a,\"b\n", b',').is_err());
+    }
+
+    #[test]
+    fn written_fields_read_back() {
+        for field in ["plain", "a,b", "say \"hi\"", "two\r\nlines"] {
+            let mut written = vec![];
+            write_field(&mut written, field.as_bytes()).unwrap();
+            written.push(b'\n');
+            assert_eq!(records(&written, b',').unwrap(), [record(1, &[field])]);
+        }
+    }
+}
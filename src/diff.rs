@@ -0,0 +1,81 @@
+//! Finding the lines added to or removed from a list, using Myers' algorithm
+//! for the longest common subsequence.
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Edit {
+    /// The line is in both lists.
+    Keep,
+    /// The line is only in the first list.
+    Delete,
+    /// The line is only in the second list.
+    Insert,
+}
+
+/// Returns the shortest list of edits turning `a` into `b`, or `None` if more
+/// than `max` lines would have to be deleted or inserted.
+pub fn diff<T>(a: &[T], b: &[T], max: usize) -> Option<Vec<Edit>>
+where
+    T: PartialEq,
+{
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = max.min(a.len() + b.len());
+    let offset = max as isize + 1;
+    // The furthest position in `a` reached on each diagonal `x - y`.
+    let mut v = vec![0; 2 * max + 3];
+    // The diagonals -d - 1..=d + 1 of `v` before each step `d`.
+    let mut trace = vec![];
+    for d in 0..=max as isize {
+        trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let index = (offset + k) as usize;
+            let mut x = if k == -d || (k != d && v[index - 1] < v[index + 1]) {
+                v[index + 1]
+            } else {
+                v[index - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index] = x;
+            if x >= n && y >= m {
+                return Some(backtrack(&trace, n, m));
+            }
+        }
+    }
+    None
+}
+
+fn backtrack(trace: &[Vec<isize>], n: isize, m: isize) -> Vec<Edit> {
+    let (mut x, mut y) = (n, m);
+    let mut edits = vec![];
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let at = |k: isize| v[(k + d + 1) as usize];
+        let k = x - y;
+        let previous_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = at(previous_k);
+        let previous_y = previous_x - previous_k;
+        while x > previous_x && y > previous_y {
+            edits.push(Edit::Keep);
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            edits.push(if x == previous_x {
+                Edit::Insert
+            } else {
+                Edit::Delete
+            });
+        }
+        x = previous_x;
+        y = previous_y;
+    }
+    edits.reverse();
+    edits
+}
@@ -0,0 +1,80 @@
+//! Descriptors of the directories files are renamed in, kept open so that
+//! a directory is opened once rather than for every rename.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::env;
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::normalize;
+
+/// Most directories kept open at a time.
+const MAX_OPEN: usize = 256;
+
+/// Open directories by their device and inode numbers, with the absolute
+/// path they were opened by.
+type Open = HashMap<(u64, u64), (PathBuf, Rc<fs::File>)>;
+
+thread_local! {
+    static OPEN: RefCell<Open> = RefCell::new(HashMap::new());
+}
+
+/// Opens the parent directory of `path` without following it for reading, or
+/// reuses the descriptor opened before, and returns it together with the
+/// final path component. Descriptors are looked up by the directory the
+/// parent currently is, so a directory that was moved or replaced, or one
+/// reached through a symbolic link and `..`, is never mistaken for another.
+pub fn open_parent(path: &Path) -> io::Result<(Rc<fs::File>, CString)> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::from_raw_os_error(libc::EINVAL))?;
+    let name = CString::new(name.as_bytes())?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let metadata = fs::metadata(parent)?;
+    let key = (metadata.dev(), metadata.ino());
+    if let Some((_, dir)) = OPEN.with(|open| open.borrow().get(&key).cloned()) {
+        return Ok((dir, name));
+    }
+    let dir = fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_PATH | libc::O_DIRECTORY)
+        .open(parent)?;
+    // The parent may have changed since it was looked up.
+    let metadata = dir.metadata()?;
+    let key = (metadata.dev(), metadata.ino());
+    let dir = Rc::new(dir);
+    let path = normalize(&env::current_dir()?.join(parent));
+    OPEN.with(|open| {
+        let mut open = open.borrow_mut();
+        if open.len() >= MAX_OPEN {
+            open.clear();
+        }
+        open.insert(key, (path, dir.clone()));
+    });
+    Ok((dir, name))
+}
+
+/// Closes the descriptors of `dir` and the directories below it after `dir`
+/// was moved or replaced, since they are unlikely to be used again.
+pub fn forget(dir: &Path) {
+    let dir = match env::current_dir() {
+        Ok(cwd) => normalize(&cwd.join(dir)),
+        Err(_) => {
+            OPEN.with(|open| open.borrow_mut().clear());
+            return;
+        }
+    };
+    OPEN.with(|open| {
+        open.borrow_mut()
+            .retain(|_, (path, _)| !path.starts_with(&dir))
+    });
+}
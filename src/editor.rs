@@ -0,0 +1,66 @@
+//! Letting the user edit the list of names.
+
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::process::Command;
+
+use tempfile::NamedTempFile;
+
+use crate::{Context, Error, Operation};
+
+pub trait Editor {
+    /// Lets the user edit `lines`, the names and comment lines of the buffer,
+    /// and returns the lines of the edited buffer.
+    fn edit(&self, lines: &[Vec<u8>]) -> Result<Vec<Vec<u8>>, Error>;
+}
+
+/// The program named by `$EDITOR`, or vi, run on the terminal on a temporary
+/// file holding the lines.
+pub struct External;
+
+impl External {
+    fn run(&self, path: &Path) -> Result<(), Error> {
+        let editor = env::var("EDITOR").unwrap_or_else(|_| "vi".into());
+        let mut command = Command::new(&editor);
+        command.arg(path);
+        if unsafe { libc::isatty(io::stdin().lock().as_raw_fd()) == 0 } {
+            let tty = Path::new("/dev/tty");
+            command.stdin(fs::File::open(tty).with_path(Operation::Read, tty)?);
+        }
+        let status = command
+            .status()
+            .with_path(Operation::Run, Path::new(&editor))?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(Error::Editor)
+        }
+    }
+}
+
+impl Editor for External {
+    fn edit(&self, lines: &[Vec<u8>]) -> Result<Vec<Vec<u8>>, Error> {
+        let temp = NamedTempFile::new().with_path(Operation::CreateIn, &env::temp_dir())?;
+        let mut writer = io::BufWriter::new(temp.as_file());
+        lines
+            .iter()
+            .try_for_each(|line| writer.write_all(line).and_then(|_| writer.write_all(b"\n")))
+            .and_then(|_| writer.flush())
+            .with_path(Operation::Write, temp.path())?;
+        drop(writer);
+        self.run(temp.path())?;
+        // Editors may replace the file, so it is read again by its name.
+        let content = fs::read(temp.path()).with_path(Operation::Read, temp.path())?;
+        let mut lines: Vec<_> = content
+            .split(|&byte| byte == b'\n')
+            .map(Vec::from)
+            .collect();
+        if lines.last().is_some_and(Vec::is_empty) {
+            lines.pop();
+        }
+        Ok(lines)
+    }
+}
@@ -0,0 +1,386 @@
+//! Queries about the file systems files reside on.
+
+use std::collections::BTreeMap;
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::mem::MaybeUninit;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+#[cfg(target_os = "linux")]
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use crate::quote::Quote;
+use crate::{absolute, parent_dir, Context, Error, Operation};
+
+/// Returns the mount point of the file system with device number `dev` that
+/// contains `path`.
+pub fn mount_point(path: &Path, dev: u64) -> PathBuf {
+    let mut top = path;
+    while let Some(parent) = top.parent() {
+        match fs::metadata(parent) {
+            Ok(metadata) if metadata.dev() == dev => top = parent,
+            _ => break,
+        }
+    }
+    top.to_owned()
+}
+
+/// Returns the mount point of the file system that `path` is on or, if it
+/// does not exist, would be created on.
+pub fn mount_of(path: &Path) -> io::Result<PathBuf> {
+    let mut path = path;
+    let metadata = loop {
+        match fs::symlink_metadata(path) {
+            Ok(metadata) => break metadata,
+            Err(_) if path != Path::new(".") && path.parent().is_some() => path = parent_dir(path),
+            Err(err) => return Err(err),
+        }
+    };
+    Ok(mount_point(&absolute(path)?, metadata.dev()))
+}
+
+/// Returns the number of bytes available to unprivileged users on the file
+/// system containing `path`.
+pub fn available_space(path: &Path) -> io::Result<u64> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stat = MaybeUninit::<libc::statvfs>::zeroed();
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let stat = unsafe { stat.assume_init() };
+    // The field types differ between platforms.
+    #[allow(clippy::unnecessary_cast)]
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+/// The maximum lengths of file names and paths on a file system, in bytes.
+#[derive(Clone, Copy)]
+pub struct NameLimits {
+    pub name_max: Option<usize>,
+    pub path_max: Option<usize>,
+}
+
+/// Returns `None` for limits that are not defined or cannot be determined.
+fn pathconf(path: &CString, name: libc::c_int) -> Option<usize> {
+    match unsafe { libc::pathconf(path.as_ptr(), name) } {
+        -1 => None,
+        value => Some(value as usize),
+    }
+}
+
+/// Returns the name length limits of the file system containing `dir`.
+pub fn name_limits(dir: &Path) -> io::Result<NameLimits> {
+    let dir = CString::new(dir.as_os_str().as_bytes())?;
+    Ok(NameLimits {
+        name_max: pathconf(&dir, libc::_PC_NAME_MAX),
+        path_max: pathconf(&dir, libc::_PC_PATH_MAX),
+    })
+}
+
+/// Magic numbers of file systems that compare names case-insensitively.
+#[cfg(target_os = "linux")]
+const CASE_INSENSITIVE_FILESYSTEMS: &[u32] = &[
+    0x4d44,     // FAT
+    0x2011bab0, // exFAT
+    0x5346544e, // NTFS (ntfs-3g, ntfs)
+    0x7366746e, // NTFS (ntfs3)
+    0xff534d42, // CIFS
+    0xfe534d42, // SMB2
+];
+
+#[cfg(target_os = "linux")]
+const FS_IOC_GETFLAGS: libc::c_ulong = 0x80086601;
+#[cfg(target_os = "linux")]
+const FS_CASEFOLD_FL: libc::c_long = 0x40000000;
+
+/// Returns true if names in the directory `dir` are compared
+/// case-insensitively, either because of the file system type or because
+/// the directory has case folding enabled.
+#[cfg(target_os = "linux")]
+pub fn is_case_insensitive(dir: &Path) -> io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+    let file = fs::File::open(dir)?;
+    let mut stat = MaybeUninit::<libc::statfs>::zeroed();
+    if unsafe { libc::fstatfs(file.as_raw_fd(), stat.as_mut_ptr()) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let stat = unsafe { stat.assume_init() };
+    if CASE_INSENSITIVE_FILESYSTEMS.contains(&(stat.f_type as u32)) {
+        return Ok(true);
+    }
+    let mut flags: libc::c_long = 0;
+    if unsafe { libc::ioctl(file.as_raw_fd(), FS_IOC_GETFLAGS, &mut flags) } == -1 {
+        // Not supported by this file system.
+        return Ok(false);
+    }
+    Ok(flags & FS_CASEFOLD_FL != 0)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn is_case_insensitive(_: &Path) -> io::Result<bool> {
+    Ok(false)
+}
+
+/// Returns the total size of the files in the tree rooted at `path`. Holes
+/// in sparse files are not counted as they are preserved when copying.
+pub fn tree_size(path: &Path) -> io::Result<u64> {
+    let metadata = fs::symlink_metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len().min(metadata.blocks() * 512));
+    }
+    let mut size = metadata.len();
+    for entry in fs::read_dir(path)? {
+        size += tree_size(&entry?.path())?;
+    }
+    Ok(size)
+}
+
+/// Formats a size in bytes for display using binary units.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// How a file is moved to its destination.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Strategy {
+    /// Renamed within its file system.
+    Rename,
+    /// Cloned to another subvolume of its file system and removed, which
+    /// falls back to copying where the data cannot be shared.
+    Clone,
+    /// Copied to another file system and removed.
+    Copy,
+}
+
+impl Strategy {
+    pub fn name(self) -> &'static str {
+        match self {
+            Strategy::Rename => "rename",
+            Strategy::Clone => "clone+unlink",
+            Strategy::Copy => "copy+unlink",
+        }
+    }
+}
+
+/// Btrfs, whose subvolumes have their own device numbers but can share
+/// data.
+#[cfg(target_os = "linux")]
+const BTRFS_MAGIC: u32 = 0x9123683e;
+
+/// From <linux/btrfs.h>, with the 1024 byte `btrfs_ioctl_fs_info_args`.
+#[cfg(target_os = "linux")]
+const BTRFS_IOC_FS_INFO: libc::c_ulong = 0x8400941f;
+
+/// Returns the UUID of the Btrfs file system containing `path`, which is
+/// the same for all of its subvolumes.
+#[cfg(target_os = "linux")]
+fn btrfs_fsid(path: &Path) -> io::Result<[u8; 16]> {
+    let file = fs::File::open(path)?;
+    let mut info = [0u8; 1024];
+    if unsafe { libc::ioctl(file.as_raw_fd(), BTRFS_IOC_FS_INFO, info.as_mut_ptr()) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut fsid = [0; 16];
+    fsid.copy_from_slice(&info[16..32]);
+    Ok(fsid)
+}
+
+#[cfg(target_os = "linux")]
+fn filesystem_type(path: &Path) -> io::Result<u32> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stat = MaybeUninit::<libc::statfs>::zeroed();
+    if unsafe { libc::statfs(path.as_ptr(), stat.as_mut_ptr()) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { stat.assume_init() }.f_type as u32)
+}
+
+/// Returns how `source` will be moved to `dest`, judged by the file systems
+/// of `source` and of the closest existing directory containing `dest`.
+pub fn strategy(source: &Path, dest: &Path) -> io::Result<Strategy> {
+    let source_dev = fs::symlink_metadata(source)?.dev();
+    let mut dir = parent_dir(dest);
+    let dest_dev = loop {
+        match fs::metadata(dir) {
+            Ok(metadata) => break metadata.dev(),
+            Err(_) if dir != Path::new(".") && dir.parent().is_some() => dir = parent_dir(dir),
+            Err(err) => return Err(err),
+        }
+    };
+    if source_dev == dest_dev {
+        return Ok(Strategy::Rename);
+    }
+    // Only subvolumes of the same file system can share data.
+    #[cfg(target_os = "linux")]
+    {
+        let source_dir = parent_dir(source);
+        if filesystem_type(source_dir)? == BTRFS_MAGIC
+            && filesystem_type(dir)? == BTRFS_MAGIC
+            && btrfs_fsid(source_dir)? == btrfs_fsid(dir)?
+        {
+            return Ok(Strategy::Clone);
+        }
+    }
+    Ok(Strategy::Copy)
+}
+
+/// A file system without enough space for the files copied to it.
+pub struct Shortage {
+    pub mount_point: PathBuf,
+    pub required: u64,
+    pub available: u64,
+}
+
+/// Checks that every file system receiving files from another file system
+/// has room for them, including those receiving them in directories yet to
+/// be created. Returns the file systems that do not.
+pub fn check_space<'a, I>(moves: I) -> Result<Vec<Shortage>, Error>
+where
+    I: IntoIterator<Item = (&'a Path, &'a Path)>,
+{
+    let mut required: BTreeMap<u64, (PathBuf, u64)> = BTreeMap::new();
+    for (source, dest) in moves {
+        let source_dev = match fs::symlink_metadata(source) {
+            Ok(metadata) => metadata.dev(),
+            Err(_) => continue,
+        };
+        // Missing directories are created on the file system of the closest
+        // existing one.
+        let mut dest_dir = parent_dir(dest);
+        let dest_dev = loop {
+            match fs::metadata(dest_dir) {
+                Ok(metadata) => break Some(metadata.dev()),
+                Err(_) if dest_dir != Path::new(".") && dest_dir.parent().is_some() => {
+                    dest_dir = parent_dir(dest_dir)
+                }
+                Err(_) => break None,
+            }
+        };
+        let dest_dev = match dest_dev {
+            Some(dest_dev) => dest_dev,
+            None => continue,
+        };
+        if source_dev == dest_dev {
+            continue;
+        }
+        let size = tree_size(source).with_path(Operation::Inspect, source)?;
+        let entry = required
+            .entry(dest_dev)
+            .or_insert_with(|| (dest_dir.to_owned(), 0));
+        entry.1 += size;
+    }
+    let mut shortages = vec![];
+    for (dev, (dir, size)) in required {
+        let available = available_space(&dir).with_path(Operation::Inspect, &dir)?;
+        if size > available {
+            let dir = dir.canonicalize().unwrap_or(dir);
+            shortages.push(Shortage {
+                mount_point: mount_point(&dir, dev),
+                required: size,
+                available,
+            });
+        }
+    }
+    Ok(shortages)
+}
+
+/// A directory that files cannot be renamed into or out of.
+pub struct Unwritable {
+    pub dir: PathBuf,
+    pub reason: String,
+}
+
+/// Returns why the effective user cannot add or remove entries in `dir`, if
+/// it cannot.
+fn access_problem(dir: &Path) -> Option<String> {
+    let path = CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let result = unsafe {
+        libc::faccessat(
+            libc::AT_FDCWD,
+            path.as_ptr(),
+            libc::W_OK | libc::X_OK,
+            libc::AT_EACCESS,
+        )
+    };
+    match result {
+        -1 => Some(io::Error::last_os_error().to_string()),
+        _ => None,
+    }
+}
+
+/// Returns why the effective user cannot remove or replace `entry` in `dir`
+/// if `dir` is sticky, which only allows that to the owners of the entry
+/// and of the directory.
+fn sticky_problem(dir: &fs::Metadata, entry: &Path) -> Option<String> {
+    let uid = unsafe { libc::geteuid() };
+    // The type of the mode differs between platforms.
+    #[allow(clippy::unnecessary_cast)]
+    let sticky = libc::S_ISVTX as u32;
+    if dir.mode() & sticky == 0 || uid == 0 || dir.uid() == uid {
+        return None;
+    }
+    match fs::symlink_metadata(entry) {
+        Ok(metadata) if metadata.uid() != uid => Some(format!(
+            "the directory is sticky and {} belongs to another user",
+            entry.quoted()
+        )),
+        _ => None,
+    }
+}
+
+/// Checks that the effective user can rename the sources of `moves` out of
+/// their directories and the destinations into theirs. Missing destination
+/// directories are checked at the closest existing ancestor, where they are
+/// created, with `parents` and skipped otherwise. Returns the directories
+/// that fail, each with the first problem found.
+pub fn check_writable<'a, I>(moves: I, parents: bool) -> Vec<Unwritable>
+where
+    I: IntoIterator<Item = (&'a Path, &'a Path)>,
+{
+    let mut problems: BTreeMap<&Path, String> = BTreeMap::new();
+    let mut check = |dir: &'a Path, entry: &Path| {
+        if problems.contains_key(dir) {
+            return;
+        }
+        let metadata = match fs::metadata(dir) {
+            Ok(metadata) => metadata,
+            Err(_) => return,
+        };
+        if let Some(problem) = access_problem(dir).or_else(|| sticky_problem(&metadata, entry)) {
+            problems.insert(dir, problem);
+        }
+    };
+    for (source, dest) in moves {
+        check(parent_dir(source), source);
+        let mut dir = parent_dir(dest);
+        if parents {
+            while fs::symlink_metadata(dir).is_err()
+                && dir != Path::new(".")
+                && dir.parent().is_some()
+            {
+                dir = parent_dir(dir);
+            }
+        }
+        check(dir, dest);
+    }
+    problems
+        .into_iter()
+        .map(|(dir, reason)| Unwritable {
+            dir: dir.to_owned(),
+            reason,
+        })
+        .collect()
+}
@@ -0,0 +1,151 @@
+//! Shell-style wildcard patterns for filtering file names.
+
+use std::path::Path;
+
+/// A pattern with `*`, `?` and `[...]` wildcards. Patterns without a slash
+/// are matched against file names, others against whole paths, in which
+/// case `*` also matches slashes.
+#[derive(Clone)]
+pub struct Pattern {
+    pattern: Vec<char>,
+    match_path: bool,
+}
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Self {
+        Pattern {
+            pattern: pattern.chars().collect(),
+            match_path: pattern.contains('/'),
+        }
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        let text = if self.match_path {
+            path.to_string_lossy()
+        } else {
+            match path.file_name() {
+                Some(name) => name.to_string_lossy(),
+                None => return false,
+            }
+        };
+        let text: Vec<char> = text.chars().collect();
+        matches(&self.pattern, &text)
+    }
+}
+
+/// Matches a bracket expression at the start of `pattern` against `c`.
+/// Returns whether it matched and the length of the expression, or `None`
+/// if the bracket is not closed.
+fn match_class(pattern: &[char], c: char) -> Option<(bool, usize)> {
+    let mut index = 1;
+    let negated = matches!(pattern.get(index), Some('!') | Some('^'));
+    if negated {
+        index += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let start = *pattern.get(index)?;
+        if start == ']' && !first {
+            return Some((matched != negated, index + 1));
+        }
+        first = false;
+        if pattern.get(index + 1) == Some(&'-')
+            && pattern.get(index + 2).is_some_and(|&end| end != ']')
+        {
+            let end = pattern[index + 2];
+            matched |= start <= c && c <= end;
+            index += 3;
+        } else {
+            matched |= start == c;
+            index += 1;
+        }
+    }
+}
+
+fn matches(pattern: &[char], text: &[char]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // Position after the last `*` and the text position it was tried at.
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        let advanced = match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, t));
+                p += 1;
+                continue;
+            }
+            Some('?') => Some(1),
+            Some('[') => match match_class(&pattern[p..], text[t]) {
+                Some((true, len)) => Some(len),
+                Some((false, _)) => None,
+                None if text[t] == '[' => Some(1),
+                None => None,
+            },
+            Some('\\') if pattern.get(p + 1) == Some(&text[t]) => Some(2),
+            Some(&c) if c == text[t] => Some(1),
+            _ => None,
+        };
+        match (advanced, backtrack) {
+            (Some(len), _) => {
+                p += len;
+                t += 1;
+            }
+            (None, Some((star, start))) => {
+                p = star;
+                t = start + 1;
+                backtrack = Some((star, start + 1));
+            }
+            (None, None) => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob(pattern: &str, text: &str) -> bool {
+        let (pattern, text): (Vec<char>, Vec<char>) =
+            (pattern.chars().collect(), text.chars().collect());
+        matches(&pattern, &text)
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(glob("*.jpg", "photo.jpg"));
+        assert!(glob("*.jpg", ".jpg"));
+        assert!(!glob("*.jpg", "photo.jpeg"));
+        assert!(glob("a*b*c", "aXbYbZc"));
+        assert!(!glob("a*b*c", "aXbYcZ"));
+        assert!(glob("IMG_????.*", "IMG_0042.raw"));
+        assert!(!glob("IMG_????.*", "IMG_042.raw"));
+        assert!(glob("**", ""));
+    }
+
+    #[test]
+    fn bracket_expressions() {
+        assert!(glob("[abc].txt", "b.txt"));
+        assert!(!glob("[!abc].txt", "b.txt"));
+        assert!(glob("[^abc].txt", "d.txt"));
+        assert!(glob("[0-9][0-9]", "42"));
+        assert!(!glob("[0-9]", "x"));
+        assert!(glob("[]]", "]"));
+        assert!(glob("[a-]", "-"));
+        assert!(glob("[ab", "[ab"));
+    }
+
+    #[test]
+    fn escapes() {
+        assert!(glob("\\*", "*"));
+        assert!(!glob("\\*", "x"));
+    }
+
+    #[test]
+    fn names_and_paths() {
+        assert!(Pattern::new("*.txt").matches(Path::new("dir/a.txt")));
+        assert!(!Pattern::new("dir*").matches(Path::new("dir/a.txt")));
+        assert!(Pattern::new("dir/*").matches(Path::new("dir/sub/a.txt")));
+        assert!(!Pattern::new("*.txt").matches(Path::new("/")));
+    }
+}
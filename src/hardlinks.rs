@@ -0,0 +1,87 @@
+//! Finding other hard links to files being renamed.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use crate::quote::Quote;
+use crate::Identity;
+
+fn walk(
+    dir: &Path,
+    wanted: &HashSet<Identity>,
+    found: &mut HashMap<Identity, Vec<PathBuf>>,
+) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = match entry.metadata() {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        };
+        if metadata.is_dir() {
+            let path = entry.path();
+            match walk(&path, wanted, found) {
+                Err(err) if skippable(&err) => eprintln!(
+                    "bulkrename: warning: not looking for hard links in {}: {}",
+                    path.quoted(),
+                    err
+                ),
+                result => result?,
+            }
+        } else if metadata.nlink() > 1 {
+            let identity = Identity {
+                dev: metadata.dev(),
+                ino: metadata.ino(),
+            };
+            if wanted.contains(&identity) {
+                found.entry(identity).or_default().push(entry.path());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether `err` only keeps a directory below the root from being searched,
+/// as it cannot be read or was removed meanwhile.
+fn skippable(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::PermissionDenied | io::ErrorKind::NotFound
+    )
+}
+
+/// Returns all paths under `root` that are hard links to any of the files
+/// in `wanted`.
+pub fn find(
+    root: &Path,
+    wanted: &HashSet<Identity>,
+) -> io::Result<HashMap<Identity, Vec<PathBuf>>> {
+    let mut found = HashMap::new();
+    walk(root, wanted, &mut found)?;
+    Ok(found)
+}
+
+/// Warns about input paths that are hard links to the same file.
+pub fn warn_shared<P>(paths: &[P], identities: &[Option<Identity>])
+where
+    P: AsRef<Path>,
+{
+    let mut groups: HashMap<Identity, Vec<&Path>> = HashMap::new();
+    for (path, identity) in paths.iter().zip(identities) {
+        if let Some(identity) = identity {
+            groups.entry(*identity).or_default().push(path.as_ref());
+        }
+    }
+    let mut shared: Vec<_> = groups.values().filter(|paths| paths.len() > 1).collect();
+    shared.sort();
+    for paths in shared {
+        let names: Vec<_> = paths.iter().map(|path| path.quoted()).collect();
+        eprintln!(
+            "bulkrename: warning: {} are hard links to the same file",
+            names.join(", ")
+        );
+    }
+}
@@ -0,0 +1,31 @@
+//! Advisory locks preventing concurrent sessions from renaming files in the
+//! same directories.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+/// Directories locked by this session. The locks are released when dropped.
+#[derive(Default)]
+pub struct Locks {
+    held: HashMap<PathBuf, File>,
+}
+
+impl Locks {
+    /// Locks the directory `dir`. Fails with `io::ErrorKind::WouldBlock` if
+    /// another session holds the lock.
+    pub fn lock(&mut self, dir: &Path) -> io::Result<()> {
+        let dir = dir.canonicalize()?;
+        if self.held.contains_key(&dir) {
+            return Ok(());
+        }
+        let file = File::open(&dir)?;
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        self.held.insert(dir, file);
+        Ok(())
+    }
+}
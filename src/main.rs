@@ -1,118 +1,1294 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::env;
 use std::ffi;
 use std::fs;
 use std::io::{self, BufRead, Write};
 use std::iter::Iterator;
+use std::mem;
 use std::os::raw;
-use std::os::unix::{ffi::OsStrExt, io::AsRawFd};
-use std::path::{Path, PathBuf};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::os::unix::{
+    ffi::{OsStrExt, OsStringExt},
+    io::AsRawFd,
+};
+use std::path::{Component, Path, PathBuf};
 use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use tempfile::NamedTempFile;
+mod ascii;
+mod blake3;
+mod collate;
+mod columns;
+mod config;
+mod conflict;
+mod copy;
+mod csv;
+mod diff;
+#[cfg(target_os = "linux")]
+mod dirs;
+mod editor;
+mod filesystem;
+mod glob;
+mod hardlinks;
+mod lock;
+mod parallel;
+mod patch;
+mod pick;
+mod plugin;
+mod quote;
+mod report;
+mod rules;
+#[cfg(target_os = "linux")]
+mod sandbox;
+mod select;
+mod sidecars;
+mod signals;
+mod symlinks;
+mod table;
+mod terminal;
+mod transform;
+mod trash;
+mod vcs;
+#[cfg(target_os = "linux")]
+mod watch;
+
+use quote::Quote;
 use thiserror::Error;
 
-const USAGE: &str = r#"usage: bulkrename [-h|--help] [FILE]...
+/// Locations that are never renamed without `--allow-system`.
+const PROTECTED: &[&str] = &[
+    "/bin", "/boot", "/dev", "/etc", "/lib", "/lib32", "/lib64", "/proc", "/sbin", "/sys", "/usr",
+];
+
+const USAGE: &str = r#"usage: bulkrename [COMMAND] [OPTION]... [FILE]...
 bulkrename is a tool for renaming large numbers of files.
 
+commands:
+  edit:              edit the names of FILEs and rename them (default)
+  plan:              edit the names of FILEs and print the renames as a CSV
+                     plan instead of performing them
+  apply PLAN:        perform the renames in the CSV plan PLAN
+  mv SOURCE DEST:    rename a single file with the same checks as other renames
+  organize [DIR]...: rename the files in DIRs, or the current directory, by the
+                     rules of the configuration file
+
+'bulkrename COMMAND --help' shows help for a command. To rename a file named
+like a command, give it as './NAME'. Files are read from standard input if no
+FILE is given or in place of a FILE of '-'.
+
 options:
   -h, --help:        display this help
-  -r, --replace:     allow replacing existing files
+  -r, --replace[=WHEN]:
+                     allow replacing existing files 'always' (default), or only
+                     when the source is 'newer', 'older' or 'larger'; other
+                     files are skipped
   -q, --quiet:       do not display information about operations being performed
+  -i, --interactive: ask what to do when a destination already exists
+  --preset=NAME:     fill in new names with the template of the preset NAME
+                     from the configuration file before editing
+  --plugin=NAME:     fill in new names with those printed by the plugin NAME,
+                     a program in $XDG_CONFIG_HOME/bulkrename/plugins that
+                     reads paths from standard input and prints a new path
+                     for each of them, one per line
+  --lowercase:       convert new names to lowercase before editing
+  --uppercase:       convert new names to uppercase before editing
+  --ascii:           transliterate new names to ASCII before editing, replacing
+                     accented letters and typographic punctuation with their
+                     closest equivalents and other characters with '_'
+  --only-stem:       apply conversions only to names without their extensions
+  --only-ext:        apply conversions only to extensions
+  --keep-ext[=ACTION]:
+                     refuse new names with a different extension ('refuse',
+                     default) or 'restore' the original extension
+  --mode-column:     show permissions in octal before each name and change
+                     the permissions of files whose mode was edited
+  --owner-column:    show 'user:group' before each name and change the owner
+                     of files whose owner was edited
+  --time-column:     show the modification time in ISO 8601 format before each
+                     name and change the times that were edited
+  --xattr-column=NAME:
+                     show the extended attribute NAME, such as user.xdg.tags,
+                     before each name and change the values that were edited;
+                     an empty value removes the attribute
+  --preserve-whitespace:
+                     keep spaces and tabs at the start and end of edited
+                     names, which are removed with a warning by default
+  --group:           group files by directory in the editor under '# ── DIR ──'
+                     comment lines, which are ignored when reading names back
+  --buffer-encoding=ENCODING:
+                     show names in the editor as 'utf8', the default, or as
+                     'latin1', where each byte is a character, for names in
+                     a legacy encoding
+  --sort=ORDER:      sort the names in the editor by their 'bytes' or by the
+                     collation rules of the 'locale' instead of keeping the
+                     order they were given in
+  --trim-prefix:     leave the directory all files are in out of the names in the
+                     editor and show it in a comment line instead; names that
+                     are absolute paths are not put back in that directory
+  --table:           show the planned renames as a table, marking moves to other
+                     directories with 'M' and flagging existing or duplicate
+                     destinations and symbolic links, which are renamed
+                     themselves; moves between file systems are grouped by
+                     mount point with the amount of data to copy; asks
+                     before renaming, --yes renames without asking and
+                     --batch only shows the table
+  --chunk=N:         edit the names in rounds of N files, in separate editor
+                     sessions, and rename all files after the last round
+  --diff:            print the renames as a unified diff of the list of names
+                     instead of performing them, implies 'plan'
+  --watch:           keep running and rename files written or moved into the
+                     directories given as FILEs by the rules of the
+                     configuration file; implies --no-sandbox
+  --swap:            exchange the names of the two FILEs without an editor
+  --also-stdin:      read files from standard input after the FILEs given as
+                     arguments
+  -R, --recursive:   list the contents of directories instead of the directories
+  --dereference:     rename the files that symbolic links point to instead of
+                     the links, which are renamed by default
+  --dangling=POLICY: 'include' (default), 'skip' or report an 'error' for
+                     symbolic links to missing files found with --recursive
+  --only=PATTERN:    only rename files matching PATTERN, which may contain the
+                     wildcards '*', '?' and '[...]' and is matched against
+                     file names or, if it contains '/', against whole paths
+  --exclude=PATTERN: do not rename files matching PATTERN
+  --pick:            narrow the list down by fuzzy matching before editing
+  --select:          choose the files to rename from a list before editing
+  -y, --yes:         answer prompts with yes, overwriting existing destinations
+  --batch:           never prompt, skipping files that would need an answer
+  -n, --auto-number: number destinations that already exist, as in 'name (1).ext'
+  --number-format=FORMAT:
+                     format for --auto-number, '{}' is replaced by the number
+  --if-identical=ACTION:
+                     when a destination already exists with the same contents,
+                     'skip' the file or 'remove' it instead of failing
+  -p, --parents:     create missing directories new names are placed in
+  --dir-mode=MODE:   give directories created for new names the octal MODE,
+                     such as 2775, instead of the one left by the umask
+  -m, --merge:       merge directories into existing directories
+  --ignore-missing:  skip input files that do not exist
+  -k, --keep-going:  continue after a failed rename and report failures at the end
+  --stop-on-error:   stop at the first failed rename (default)
+  --retries=N:       retry renames failing with transient errors up to N times
+  --no-sandbox:      do not restrict modifications to the involved directories
+  --protect=DIR:     refuse to rename files in DIR
+  --allow-system:    allow renaming the root directory and protected locations
+  --allow-control-chars:
+                     allow new names to contain control characters, such as
+                     line breaks, that the original names did not
+  -e, --expand:      expand '~' and environment variables in new names
+  --relative-to-source:
+                     place new names without a directory next to the original
+  --print-renamed:   print only the new paths of renamed files, one per line
+  --print-renamed0:  like --print-renamed but separate paths with null bytes
+  --from-csv=FILE:   read sources and destinations from the first two columns of
+                     a CSV file (TSV if FILE ends with .tsv) instead of using
+                     an editor; a third column may contain 'replace' or
+                     'backup' to replace an existing destination
+  --apply-diff=FILE: perform the renames in a unified diff of the list of names,
+                     as printed with --diff or by 'diff -u' of two listings;
+                     the names removed and added by each group of changed
+                     lines are paired up in order
+  --report=FILE:     write the source, destination, status, strategy, error and
+                     duration of each rename to FILE, as JSON if FILE ends
+                     with .json and as CSV otherwise; JSON gives bytes that
+                     are not UTF-8 as '\udc80' to '\udcff'
+  -a, --absolute:    show and rename files using absolute paths
+  -g, --git:         rename files tracked by Git with 'git mv'
+  --vcs=NAME:        rename tracked files through version control, NAME is
+                     one of 'git', 'hg', 'jj' or 'auto'
+  --fix-symlinks:    update symbolic links among the files whose targets were renamed
+  --fix-symlinks-in=DIR:
+                     also update symbolic links inside DIR
+  --retarget-links:  change the relative targets of symbolic links moved to
+                     another directory so that they point to the same file
+  --sidecars=EXTS:   also rename files with one of the comma-separated
+                     extensions EXTS, such as xmp,srt, whose name is the
+                     name or the name without extension of a renamed file
+  --rename-hardlinks=DIR:
+                     give other hard links inside DIR to renamed files the same new name
+  --context=MODE:    security context of files copied across file systems, MODE
+                     is 'preserve' (default) or 'default' for the destination's
+  --preserve=LIST:   attributes of files copied across file systems to preserve,
+                     LIST is a comma separated list of 'mode', 'ownership',
+                     'timestamps', 'xattr', 'links' or 'all' (default:
+                     mode,ownership,timestamps,xattr,links); ownership that
+                     cannot be preserved without root privileges is reported
+  --verify:          compare checksums of files copied across file systems
+                     before removing the originals
+  --sync[=WHEN]:     flush the directories of renamed files to disk after all
+                     renames ('batch', default) or after 'each' rename
+  --on-stale=POLICY: what to do with files modified, replaced or removed while
+                     the file list was being edited, POLICY is 'abort'
+                     (default), 'skip' or 'ask'
+  --max=N:           refuse to perform more than N renames (default: 10000)
+  --no-limit:        do not limit the number of renames
+  -b, --backup:      keep numbered backups of replaced files
+  --suffix=SUFFIX:   back up replaced files by appending SUFFIX to their names
+  --backup-dir=DIR:  back up replaced files into DIR, preserving their paths
+  --no-trash:        delete replaced files instead of moving them to the trash
+
+configuration is read from $XDG_CONFIG_HOME/bulkrename/config, which may
+contain 'protect = PATTERN' lines naming files that are never renamed and
+'protect-action = refuse|skip' to either refuse to run or skip those files.
+'rule = PATTERN -> TEMPLATE' lines give files matching PATTERN the new path
+TEMPLATE, relative to their directory, in which {name}, {stem}, {ext}, {year},
+{month} and {day} are replaced by the name, the name without and the extension
+and the date of the last modification of the file, and {name|ascii},
+{stem|ascii} and {ext|ascii} by the transliteration to ASCII.
+'preset NAME = TEMPLATE' lines define templates of the same form for --preset
+"#;
+
+const PLAN_USAGE: &str = r#"usage: bulkrename plan [OPTION]... [FILE]...
+Lets you edit the names of FILEs, or of the files listed on standard input,
+and prints the renames as a CSV plan with 'source' and 'destination' columns
+instead of performing them, or as a unified diff of the list of names with
+--diff. The 'strategy' column tells how each file will be moved: by a
+'rename', or by 'clone+unlink' or 'copy+unlink' to another file system.
+Like diff, exits with status 0 if no file would be renamed, 1 if some would
+and 2 on errors. The plan can be changed and performed with 'bulkrename apply'. See
+'bulkrename --help' for options.
+"#;
+
+const APPLY_USAGE: &str = r#"usage: bulkrename apply [OPTION]... PLAN
+Performs the renames in the CSV file PLAN, as written by 'bulkrename plan' or
+described for --from-csv in 'bulkrename --help', without opening an editor.
+PLAN is read as a unified diff, as for --apply-diff, if it ends with .diff or
+.patch.
+"#;
+
+const MV_USAGE: &str = r#"usage: bulkrename mv [OPTION]... SOURCE DEST
+Renames SOURCE to DEST, refusing to replace an existing file unless --replace
+is given and applying the same checks and options as other renames, such as
+--backup, --parents and --verify.
+"#;
+
+const ORGANIZE_USAGE: &str = r#"usage: bulkrename organize [OPTION]... [DIR]...
+Renames the files in DIRs, or in the current directory, that match one of the
+'rule = PATTERN -> TEMPLATE' lines of the configuration file described in
+'bulkrename --help', creating the directories they are moved to. With --diff
+the renames are printed instead of performed. Use --watch to keep renaming
+files as they arrive.
 "#;
 
 #[derive(Error, Debug)]
 enum Error {
-    #[error("unknown option '{0}'")]
-    UnknownOption(String),
+    #[error("unknown option '{}'", .0.quoted())]
+    UnknownOption(ffi::OsString),
+    #[error("'{0}' expects {1}")]
+    InvalidArguments(&'static str, &'static str),
+    #[error("option '{0}' requires a value")]
+    MissingValue(String),
+    #[error("invalid value '{}' for option '{}'", .1.quoted(), .0)]
+    InvalidValue(String, ffi::OsString),
     #[error("invalid file list")]
     InvalidFileList,
+    #[error("'{0}' contains characters that cannot be encoded in {1}")]
+    Unencodable(String, &'static str),
+    #[error("the edited file list has {1} names instead of {0}")]
+    LineCount(usize, usize),
     #[error("editor exited with a non-zero return code")]
     Editor,
+    #[error("could not merge '{}': {} conflicting entries left in place", .0.quoted(), .1)]
+    MergeConflict(PathBuf, usize),
+    #[error("refusing to rename {} ({}), use --allow-system to override", .0.quoted(), .1)]
+    DangerousPath(PathBuf, &'static str),
+    #[error("{} contains control characters, use --allow-control-chars to allow them", .0.quoted())]
+    ControlCharacters(PathBuf),
+    #[error("{} is a dangling symbolic link, there is no file to rename", .0.quoted())]
+    DanglingLink(PathBuf),
+    #[error("{} is a dangling symbolic link, use --dangling=include or --dangling=skip to allow it", .0.quoted())]
+    DanglingEntry(PathBuf),
+    #[error("{} is a symbolic link that leads to itself or through too many links", .0.quoted())]
+    LinkLoop(PathBuf),
+    #[error("refusing to perform {0} renames, more than the limit of {1}; use --max or --no-limit to override")]
+    TooManyChanges(usize, usize),
+    #[error("environment variable '{0}' is not defined")]
+    UndefinedVariable(String),
+    #[error("{} has a name longer than the file system's limit of {} bytes", .0.quoted(), .1)]
+    NameTooLong(PathBuf, usize),
+    #[error("{} is longer than the file system's limit of {} bytes", .0.quoted(), .1)]
+    PathTooLong(PathBuf, usize),
+    #[error("{} and {} differ only by case on a case-insensitive file system", .0.quoted(), .1.quoted())]
+    CaseCollision(PathBuf, PathBuf),
+    #[error("invalid {} '{}'", .0, .1.quoted())]
+    InvalidAttribute(String, String),
+    #[error("{0} new names change the extension, use --keep-ext=restore to keep the original extensions")]
+    ExtensionsChanged(usize),
+    #[error("{}, line {}: {}", .0.quoted(), .1, .2)]
+    InvalidConfig(PathBuf, usize, String),
+    #[error("cancelled")]
+    Cancelled,
+    #[error("no rules are defined in the configuration file")]
+    NoRules,
+    #[error("no preset named '{0}' is defined in the configuration file")]
+    UnknownPreset(String),
+    #[error("plugin {}: {}", .0.quoted(), .1)]
+    Plugin(PathBuf, String),
+    #[error("invalid plan on line {0}: {1}")]
+    InvalidPlan(usize, String),
+    #[error("not enough free space for copying files across file systems")]
+    InsufficientSpace,
+    #[error("some directories do not allow renaming files in them")]
+    NotWritable,
+    #[error("another bulkrename session is active in {0}")]
+    Locked(PathBuf),
+    #[error("{0} files changed while the file list was being edited")]
+    Stale(usize),
+    #[error("interrupted after renaming {0} of {1} files")]
+    Interrupted(usize, usize),
+    /// Not an error: a plan has renames to perform.
+    #[error("renames are pending")]
+    Pending,
+    /// An error while making a plan.
+    #[error("{0}")]
+    Planning(Box<Error>),
+    #[error("{0} files could not be renamed")]
+    Failures(usize),
+    #[error("line {0}: {1}")]
+    Line(usize, Box<Error>),
+    /// An operation on `path`, or on `path` and `destination`, failed.
+    #[error(
+        "cannot {} {}{}: {}",
+        .operation.verb(),
+        .path.quoted(),
+        .destination.as_ref().map_or_else(String::new, |destination| format!(" {} {}", .operation.preposition(), destination.quoted())),
+        .source
+    )]
+    Failed {
+        operation: Operation,
+        path: PathBuf,
+        destination: Option<PathBuf>,
+        source: io::Error,
+    },
     #[error(transparent)]
     Io(#[from] io::Error),
 }
 
+impl Error {
+    /// Attributes the error to a line of the edited buffer or plan. Line 0
+    /// stands for files given in some other way.
+    fn at_line(self, line: usize) -> Error {
+        match line {
+            0 => self,
+            _ => Error::Line(line, Box::new(self)),
+        }
+    }
+}
+
+/// A file system operation, named in errors about it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operation {
+    Rename,
+    Exchange,
+    Compare,
+    Remove,
+    List,
+    Read,
+    Write,
+    CreateDir,
+    /// Creating a temporary file in a directory.
+    CreateIn,
+    Inspect,
+    Lock,
+    Sync,
+    UpdateLink,
+    Run,
+}
+
+impl Operation {
+    fn verb(self) -> &'static str {
+        match self {
+            Operation::Rename => "rename",
+            Operation::Exchange => "exchange",
+            Operation::Compare => "compare",
+            Operation::Remove => "remove",
+            Operation::List => "list",
+            Operation::Read => "read",
+            Operation::Write => "write",
+            Operation::CreateDir => "create directory",
+            Operation::CreateIn => "create a file in",
+            Operation::Inspect => "access",
+            Operation::Lock => "lock",
+            Operation::Sync => "flush",
+            Operation::UpdateLink => "update link",
+            Operation::Run => "run",
+        }
+    }
+
+    /// The word between the two paths of the operation.
+    fn preposition(self) -> &'static str {
+        match self {
+            Operation::Exchange | Operation::Compare => "with",
+            _ => "to",
+        }
+    }
+}
+
+/// Attaching the failed operation and the paths involved to I/O errors.
+trait Context<T> {
+    fn with_path(self, operation: Operation, path: &Path) -> Result<T, Error>;
+    fn with_paths(self, operation: Operation, path: &Path, destination: &Path) -> Result<T, Error>;
+}
+
+impl<T> Context<T> for io::Result<T> {
+    fn with_path(self, operation: Operation, path: &Path) -> Result<T, Error> {
+        self.map_err(|source| Error::Failed {
+            operation,
+            path: path.to_owned(),
+            destination: None,
+            source,
+        })
+    }
+
+    fn with_paths(self, operation: Operation, path: &Path, destination: &Path) -> Result<T, Error> {
+        self.map_err(|source| Error::Failed {
+            operation,
+            path: path.to_owned(),
+            destination: Some(destination.to_owned()),
+            source,
+        })
+    }
+}
+
+/// Returns the prefix of messages about `line` of the edited buffer or plan.
+fn line_prefix(line: usize) -> String {
+    match line {
+        0 => String::new(),
+        _ => format!("line {}: ", line),
+    }
+}
+
+#[derive(Clone)]
+enum Backup {
+    None,
+    Trash,
+    Numbered,
+    Suffix(String),
+    Directory(PathBuf),
+}
+
+/// What to do with files that changed while the file list was being edited.
+#[derive(Clone, Copy)]
+enum Stale {
+    Abort,
+    Skip,
+    Ask,
+}
+
+/// What to do with new names whose extension differs from the original.
+#[derive(Clone, Copy, PartialEq)]
+enum KeepExtension {
+    Refuse,
+    Restore,
+}
+
+/// The order of the names in the editor.
+#[derive(Clone, Copy, PartialEq)]
+enum Sort {
+    /// By the bytes of the names.
+    Bytes,
+    /// By the collation rules of the locale.
+    Locale,
+}
+
+/// The character encoding of the names in the editor.
+#[derive(Clone, Copy, PartialEq)]
+enum Encoding {
+    Utf8,
+    /// Each byte of a name is shown as the character with the same code.
+    Latin1,
+}
+
+impl Encoding {
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Latin1 => "Latin-1",
+        }
+    }
+
+    /// Converts the bytes of a name to the UTF-8 text shown in the editor.
+    fn decode(self, name: &[u8]) -> Vec<u8> {
+        match self {
+            Encoding::Utf8 => name.to_vec(),
+            Encoding::Latin1 => name
+                .iter()
+                .map(|&byte| byte as char)
+                .collect::<String>()
+                .into_bytes(),
+        }
+    }
+
+    /// Converts an edited line back to the bytes of a name.
+    fn encode(self, line: &[u8]) -> Result<Vec<u8>, Error> {
+        match self {
+            Encoding::Utf8 => Ok(line.to_vec()),
+            Encoding::Latin1 => {
+                let unencodable =
+                    || Error::Unencodable(String::from_utf8_lossy(line).into(), self.name());
+                std::str::from_utf8(line)
+                    .map_err(|_| unencodable())?
+                    .chars()
+                    .map(|c| match c as u32 {
+                        code @ 0..=0xff => Ok(code as u8),
+                        _ => Err(unencodable()),
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// When an existing destination is replaced, compared to the source.
+#[derive(Clone, Copy, PartialEq)]
+enum ReplaceIf {
+    /// The source was modified more recently.
+    Newer,
+    /// The source was modified less recently.
+    Older,
+    /// The source is larger.
+    Larger,
+}
+
+impl ReplaceIf {
+    fn name(self) -> &'static str {
+        match self {
+            ReplaceIf::Newer => "newer",
+            ReplaceIf::Older => "older",
+            ReplaceIf::Larger => "larger",
+        }
+    }
+
+    fn allows(self, source: &fs::Metadata, dest: &fs::Metadata) -> bool {
+        let modified = |metadata: &fs::Metadata| (metadata.mtime(), metadata.mtime_nsec());
+        match self {
+            ReplaceIf::Newer => modified(source) > modified(dest),
+            ReplaceIf::Older => modified(source) < modified(dest),
+            ReplaceIf::Larger => source.len() > dest.len(),
+        }
+    }
+}
+
+/// What to do with files whose destination exists with the same contents.
+#[derive(Clone, Copy, PartialEq)]
+enum Identical {
+    /// Leave the file in place.
+    Skip,
+    /// Remove the file, as if it had been renamed.
+    Remove,
+}
+
+/// What to do with dangling symbolic links found in directories listed with
+/// `--recursive`.
+#[derive(Clone, Copy, PartialEq)]
+enum Dangling {
+    Include,
+    Skip,
+    Error,
+}
+
+/// When directories affected by renames are flushed to disk.
+#[derive(Clone, Copy, PartialEq)]
+enum Sync {
+    Batch,
+    Each,
+}
+
+/// What to do, selected by the first argument.
+#[derive(Clone, Copy, PartialEq)]
+enum Command {
+    /// Edit the names and rename the files.
+    Edit,
+    /// Edit the names and print a plan.
+    Plan,
+    /// Perform the renames in a plan.
+    Apply,
+    /// Rename a single file.
+    Mv,
+    /// Rename the files in directories by the rules of the configuration.
+    Organize,
+}
+
+impl Command {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "edit" => Some(Command::Edit),
+            "plan" => Some(Command::Plan),
+            "apply" => Some(Command::Apply),
+            "mv" => Some(Command::Mv),
+            "organize" => Some(Command::Organize),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone)]
 struct Args {
+    command: Command,
     show_help: bool,
     replace: bool,
+    /// Replace existing destinations only under this condition.
+    replace_if: Option<ReplaceIf>,
     quiet: bool,
+    interactive: bool,
+    auto_number: bool,
+    number_format: String,
+    merge: bool,
+    ignore_missing: bool,
+    keep_going: bool,
+    retries: u32,
+    sandbox: bool,
+    allow_system: bool,
+    /// Allow control characters in new names.
+    allow_control_chars: bool,
+    protected: Vec<PathBuf>,
+    max: Option<usize>,
+    expand: bool,
+    relative_to_source: bool,
+    absolute: bool,
+    vcs: Vec<&'static dyn vcs::Vcs>,
+    /// How the user edits the list of names.
+    editor: &'static dyn editor::Editor,
+    fix_symlinks: bool,
+    symlink_dirs: Vec<PathBuf>,
+    /// Keep moved symbolic links pointing to the same file.
+    retarget_links: bool,
+    hardlink_root: Option<PathBuf>,
+    /// Extensions of files renamed along with the files they belong to.
+    sidecars: Vec<String>,
+    copy: copy::Options,
+    backup: Backup,
+    sync: Option<Sync>,
+    stale: Stale,
+    from_csv: Option<PathBuf>,
+    from_diff: Option<PathBuf>,
+    /// Name of the preset whose template fills in new names.
+    preset: Option<String>,
+    /// The template of the preset, looked up in the configuration file.
+    template: Option<String>,
+    /// The program proposing new names, given by its name until it is
+    /// looked up.
+    plugin: Option<PathBuf>,
+    /// Transforms applied to the names initially shown in the editor.
+    transforms: Vec<transform::Transform>,
+    transform_scope: transform::Scope,
+    keep_extension: Option<KeepExtension>,
+    if_identical: Option<Identical>,
+    /// Attributes edited in columns before the names.
+    columns: Vec<columns::Column>,
+    /// Group files by directory in the editor.
+    group: bool,
+    /// Sort the names in the editor instead of keeping the input order.
+    sort: Option<Sort>,
+    /// The encoding of the names in the editor.
+    buffer_encoding: Encoding,
+    /// Show planned renames as a table and ask before renaming.
+    table: bool,
+    /// Write plans as unified diffs instead of CSV.
+    diff: bool,
+    /// Keep renaming files arriving in the given directories by the rules.
+    watch: bool,
+    /// Exchange the names of the two given files.
+    swap: bool,
+    /// Create missing directories of destinations.
+    parents: bool,
+    /// The mode of created directories instead of the one left by the umask.
+    dir_mode: Option<u32>,
+    /// Write the outcome of each rename to this file.
+    report: Option<PathBuf>,
+    /// Read files from standard input in addition to the arguments.
+    also_stdin: bool,
+    /// Keep whitespace around edited names.
+    preserve_whitespace: bool,
+    /// Edit the names in rounds of at most this many files.
+    chunk: Option<usize>,
+    /// Leave the directory shared by all files out of the names in the editor.
+    trim_prefix: bool,
+    recursive: bool,
+    /// Rename the targets of symbolic links instead of the links.
+    dereference: bool,
+    dangling: Dangling,
+    only: Vec<glob::Pattern>,
+    exclude: Vec<glob::Pattern>,
+    select: bool,
+    pick: bool,
+    /// Answer to prompts given with `--yes` or `--batch`.
+    answer: Option<bool>,
+    /// Terminator of the new paths printed after renaming.
+    print_renamed: Option<u8>,
     files: Vec<PathBuf>,
 }
 
 impl Args {
+    /// Returns the options for a row of a plan with `flags`.
+    fn with_flags(&self, flags: csv::Flags) -> Args {
+        let mut args = self.clone();
+        if flags.replace || flags.backup {
+            args.replace = true;
+        }
+        if flags.backup {
+            if let Backup::None | Backup::Trash = args.backup {
+                args.backup = Backup::Numbered;
+            }
+        }
+        args
+    }
+
     fn parse() -> Result<Self, Error> {
+        Self::parse_from(env::args_os().skip(1))
+    }
+
+    /// Parses the arguments in `iter`, which leaves out the program name.
+    fn parse_from<I>(iter: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = ffi::OsString>,
+    {
         let mut args = Args {
+            command: Command::Edit,
             show_help: false,
             replace: false,
+            replace_if: None,
             quiet: false,
+            interactive: false,
+            auto_number: false,
+            number_format: " ({})".into(),
+            merge: false,
+            ignore_missing: false,
+            keep_going: false,
+            retries: 0,
+            sandbox: true,
+            allow_system: false,
+            allow_control_chars: false,
+            protected: PROTECTED.iter().map(From::from).collect(),
+            max: Some(10000),
+            expand: false,
+            relative_to_source: false,
+            absolute: false,
+            vcs: vec![],
+            editor: &editor::External,
+            fix_symlinks: false,
+            symlink_dirs: vec![],
+            retarget_links: false,
+            hardlink_root: None,
+            sidecars: vec![],
+            copy: copy::Options {
+                context: copy::Context::Preserve,
+                preserve: copy::Preserve {
+                    mode: true,
+                    ownership: true,
+                    timestamps: true,
+                    xattr: true,
+                    links: true,
+                },
+                verify: false,
+                sync: false,
+            },
+            backup: Backup::Trash,
+            sync: None,
+            stale: Stale::Abort,
+            from_csv: None,
+            from_diff: None,
+            preset: None,
+            template: None,
+            plugin: None,
+            transforms: vec![],
+            transform_scope: transform::Scope::Name,
+            keep_extension: None,
+            if_identical: None,
+            columns: vec![],
+            group: false,
+            sort: None,
+            buffer_encoding: Encoding::Utf8,
+            table: false,
+            diff: false,
+            watch: false,
+            swap: false,
+            parents: false,
+            also_stdin: false,
+            preserve_whitespace: false,
+            chunk: None,
+            dir_mode: None,
+            report: None,
+            trim_prefix: false,
+            recursive: false,
+            dereference: false,
+            dangling: Dangling::Include,
+            only: vec![],
+            exclude: vec![],
+            select: false,
+            pick: false,
+            answer: None,
+            print_renamed: None,
             files: vec![],
         };
-        let mut iter = env::args().skip(1);
-        for arg in &mut iter {
-            match arg.as_ref() {
+        // Arguments are kept as they are, since names need not be UTF-8.
+        let mut iter = iter.into_iter().peekable();
+        if let Some(command) = iter
+            .peek()
+            .and_then(|name| name.to_str())
+            .and_then(Command::from_name)
+        {
+            args.command = command;
+            iter.next();
+        }
+        while let Some(arg) = iter.next() {
+            let bytes = arg.as_bytes();
+            let (flag, raw_inline) = match bytes.iter().position(|&byte| byte == b'=') {
+                Some(index) if bytes.starts_with(b"--") => (
+                    &bytes[..index],
+                    Some(ffi::OsStr::from_bytes(&bytes[index + 1..])),
+                ),
+                _ => (bytes, None),
+            };
+            let flag = match std::str::from_utf8(flag) {
+                Ok(flag) => flag,
+                Err(_) if bytes.starts_with(b"-") => return Err(Error::UnknownOption(arg)),
+                Err(_) => {
+                    args.files.push(arg.into());
+                    break;
+                }
+            };
+            let inline = raw_inline.map(|inline| inline.to_string_lossy());
+            let inline = inline.as_deref();
+            let mut path = || {
+                raw_inline
+                    .map(From::from)
+                    .or_else(|| iter.next())
+                    .ok_or_else(|| Error::MissingValue(flag.into()))
+            };
+            let mut value = || {
+                path()?
+                    .into_string()
+                    .map_err(|value| Error::InvalidValue(flag.into(), value))
+            };
+            match flag {
                 "-h" | "--help" => args.show_help = true,
-                "-r" | "--replace" => args.replace = true,
+                "-r" | "--replace" => {
+                    args.replace = true;
+                    args.replace_if = match inline {
+                        None | Some("always") => None,
+                        Some("newer") => Some(ReplaceIf::Newer),
+                        Some("older") => Some(ReplaceIf::Older),
+                        Some("larger") => Some(ReplaceIf::Larger),
+                        Some(when) => return Err(Error::InvalidValue(flag.into(), when.into())),
+                    };
+                }
                 "-q" | "--quiet" => args.quiet = true,
+                "-i" | "--interactive" => args.interactive = true,
+                "--preset" => args.preset = Some(value()?),
+                "--plugin" => args.plugin = Some(path()?.into()),
+                "--lowercase" => args.transforms.push(transform::Transform::Lowercase),
+                "--uppercase" => args.transforms.push(transform::Transform::Uppercase),
+                "--ascii" => args.transforms.push(transform::Transform::Ascii),
+                "--only-stem" => args.transform_scope = transform::Scope::Stem,
+                "--only-ext" => args.transform_scope = transform::Scope::Extension,
+                "--keep-ext" => {
+                    args.keep_extension = match inline {
+                        None | Some("refuse") => Some(KeepExtension::Refuse),
+                        Some("restore") => Some(KeepExtension::Restore),
+                        Some(action) => {
+                            return Err(Error::InvalidValue(flag.into(), action.into()))
+                        }
+                    }
+                }
+                "--if-identical" => {
+                    args.if_identical = match value()?.as_ref() {
+                        "skip" => Some(Identical::Skip),
+                        "remove" => Some(Identical::Remove),
+                        action => return Err(Error::InvalidValue(flag.into(), action.into())),
+                    }
+                }
+                "--mode-column" => {
+                    if !args.columns.contains(&columns::Column::Mode) {
+                        args.columns.push(columns::Column::Mode);
+                    }
+                }
+                "--owner-column" => {
+                    if !args.columns.contains(&columns::Column::Owner) {
+                        args.columns.push(columns::Column::Owner);
+                    }
+                }
+                "--time-column" => {
+                    if !args.columns.contains(&columns::Column::Time) {
+                        args.columns.push(columns::Column::Time);
+                    }
+                }
+                "--xattr-column" => {
+                    let column = columns::Column::Xattr(value()?);
+                    if !args.columns.contains(&column) {
+                        args.columns.push(column);
+                    }
+                }
+                "--group" => args.group = true,
+                "--sort" => {
+                    args.sort = match value()?.as_ref() {
+                        "bytes" => Some(Sort::Bytes),
+                        "locale" => Some(Sort::Locale),
+                        order => return Err(Error::InvalidValue(flag.into(), order.into())),
+                    }
+                }
+                "--buffer-encoding" => {
+                    args.buffer_encoding = match value()?.as_ref() {
+                        "utf8" => Encoding::Utf8,
+                        "latin1" => Encoding::Latin1,
+                        encoding => return Err(Error::InvalidValue(flag.into(), encoding.into())),
+                    }
+                }
+                "--table" => args.table = true,
+                "--diff" => args.diff = true,
+                "--watch" => args.watch = true,
+                "--swap" => args.swap = true,
+                "-p" | "--parents" => args.parents = true,
+                "--also-stdin" => args.also_stdin = true,
+                "--preserve-whitespace" => args.preserve_whitespace = true,
+                "--chunk" => {
+                    let chunk = value()?;
+                    args.chunk = match chunk.parse() {
+                        Ok(0) | Err(_) => {
+                            return Err(Error::InvalidValue(flag.into(), chunk.into()))
+                        }
+                        Ok(chunk) => Some(chunk),
+                    };
+                }
+                "--dir-mode" => {
+                    let mode = value()?;
+                    args.dir_mode = match columns::parse_mode(&mode) {
+                        Some(mode) => Some(mode),
+                        None => return Err(Error::InvalidValue(flag.into(), mode.into())),
+                    };
+                }
+                "--trim-prefix" => args.trim_prefix = true,
+                "-R" | "--recursive" => args.recursive = true,
+                "--dereference" => args.dereference = true,
+                "--dangling" => {
+                    args.dangling = match value()?.as_ref() {
+                        "include" => Dangling::Include,
+                        "skip" => Dangling::Skip,
+                        "error" => Dangling::Error,
+                        policy => return Err(Error::InvalidValue(flag.into(), policy.into())),
+                    }
+                }
+                "--select" => args.select = true,
+                "--pick" => args.pick = true,
+                "--only" => args.only.push(glob::Pattern::new(&value()?)),
+                "--exclude" => args.exclude.push(glob::Pattern::new(&value()?)),
+                "-y" | "--yes" => args.answer = Some(true),
+                "--batch" => args.answer = Some(false),
+                "-n" | "--auto-number" => args.auto_number = true,
+                "--number-format" => {
+                    let format = value()?;
+                    if !format.contains("{}") {
+                        return Err(Error::InvalidValue(flag.into(), format.into()));
+                    }
+                    args.number_format = format;
+                }
+                "-m" | "--merge" => args.merge = true,
+                "--ignore-missing" => args.ignore_missing = true,
+                "-k" | "--keep-going" => args.keep_going = true,
+                "--stop-on-error" => args.keep_going = false,
+                "--no-sandbox" => args.sandbox = false,
+                "--protect" => args.protected.push(path()?.into()),
+                "--allow-system" => args.allow_system = true,
+                "--allow-control-chars" => args.allow_control_chars = true,
+                "--max" => {
+                    let max = value()?;
+                    args.max = Some(
+                        max.parse()
+                            .map_err(|_| Error::InvalidValue(flag.into(), max.into()))?,
+                    );
+                }
+                "--no-limit" => args.max = None,
+                "-e" | "--expand" => args.expand = true,
+                "--relative-to-source" => args.relative_to_source = true,
+                "-a" | "--absolute" => args.absolute = true,
+                "-g" | "--git" => args.vcs = vec![&vcs::Git],
+                "--fix-symlinks" => args.fix_symlinks = true,
+                "--retarget-links" => args.retarget_links = true,
+                "--fix-symlinks-in" => {
+                    args.fix_symlinks = true;
+                    args.symlink_dirs.push(path()?.into());
+                }
+                "--rename-hardlinks" => args.hardlink_root = Some(path()?.into()),
+                "--sidecars" => {
+                    args.sidecars = value()?
+                        .split(',')
+                        .map(|extension| extension.trim_start_matches('.'))
+                        .filter(|extension| !extension.is_empty())
+                        .map(From::from)
+                        .collect()
+                }
+                "--context" => {
+                    args.copy.context = match value()?.as_ref() {
+                        "preserve" => copy::Context::Preserve,
+                        "default" => copy::Context::Default,
+                        mode => return Err(Error::InvalidValue(flag.into(), mode.into())),
+                    }
+                }
+                "--preserve" => {
+                    let list = value()?;
+                    args.copy.preserve = copy::Preserve::parse(&list)
+                        .ok_or_else(|| Error::InvalidValue(flag.into(), list.into()))?;
+                }
+                "--verify" => args.copy.verify = true,
+                "--sync" => {
+                    args.sync = match inline {
+                        None | Some("batch") => Some(Sync::Batch),
+                        Some("each") => Some(Sync::Each),
+                        Some(when) => return Err(Error::InvalidValue(flag.into(), when.into())),
+                    };
+                    args.copy.sync = true;
+                }
+                "--print-renamed" => {
+                    args.print_renamed = Some(b'\n');
+                    args.quiet = true;
+                }
+                "--print-renamed0" => {
+                    args.print_renamed = Some(b'\0');
+                    args.quiet = true;
+                }
+                "--from-csv" => args.from_csv = Some(path()?.into()),
+                "--apply-diff" => args.from_diff = Some(path()?.into()),
+                "--report" => args.report = Some(path()?.into()),
+                "--on-stale" => {
+                    args.stale = match value()?.as_ref() {
+                        "abort" => Stale::Abort,
+                        "skip" => Stale::Skip,
+                        "ask" => Stale::Ask,
+                        policy => return Err(Error::InvalidValue(flag.into(), policy.into())),
+                    }
+                }
+                "--vcs" => {
+                    let name = value()?;
+                    args.vcs = match vcs::by_name(&name) {
+                        Some(vcs) => vec![vcs],
+                        None if name == "auto" => vcs::ALL.to_vec(),
+                        None => return Err(Error::InvalidValue(flag.into(), name.into())),
+                    };
+                }
+                "--retries" => {
+                    let retries = value()?;
+                    args.retries = retries
+                        .parse()
+                        .map_err(|_| Error::InvalidValue(flag.into(), retries.into()))?;
+                }
+                "-b" | "--backup" => {
+                    if let Backup::None | Backup::Trash = args.backup {
+                        args.backup = Backup::Numbered;
+                    }
+                }
+                "--no-trash" => {
+                    if let Backup::Trash = args.backup {
+                        args.backup = Backup::None;
+                    }
+                }
+                "--suffix" => args.backup = Backup::Suffix(value()?),
+                "--backup-dir" => args.backup = Backup::Directory(path()?.into()),
                 "--" => break,
+                "-" => {
+                    args.files.push(arg.into());
+                    break;
+                }
                 flag if flag.starts_with('-') => return Err(Error::UnknownOption(flag.into())),
-                file => {
-                    args.files.push(From::from(file));
+                _ => {
+                    args.files.push(arg.into());
                     break;
                 }
             }
         }
         args.files.extend(iter.map(From::from));
+        if args.show_help {
+            return Ok(args);
+        }
+        match args.command {
+            Command::Apply => match args.files.as_slice() {
+                [plan]
+                    if matches!(
+                        plan.extension().and_then(|extension| extension.to_str()),
+                        Some("diff") | Some("patch")
+                    ) =>
+                {
+                    args.from_diff = Some(plan.clone())
+                }
+                [plan] => args.from_csv = Some(plan.clone()),
+                _ => return Err(Error::InvalidArguments("apply", "a single plan")),
+            },
+            _ if args.swap && args.files.len() != 2 => {
+                return Err(Error::InvalidArguments("--swap", "two files"))
+            }
+            Command::Mv if args.files.len() != 2 => {
+                return Err(Error::InvalidArguments("mv", "a source and a destination"))
+            }
+            // Standard output is reserved for the plan.
+            Command::Plan => args.quiet = true,
+            Command::Edit if args.diff => {
+                args.command = Command::Plan;
+                args.quiet = true;
+            }
+            _ => {}
+        }
         Ok(args)
     }
 }
 
-fn source_files() -> io::Result<Vec<PathBuf>> {
-    io::stdin()
+/// Removes terminal escape sequences, such as colors added by `ls --color`,
+/// from `line`.
+fn strip_escapes(line: &str) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            // Control sequences end with a byte in the range '@'..='~'.
+            Some('[') => {
+                for c in &mut chars {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // Operating system commands end with BEL or ESC '\\'.
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' || (c == '\x1b' && chars.next_if_eq(&'\\').is_some()) {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    result
+}
+
+/// Removes a file type indicator appended by `ls -F` if `line` only names an
+/// existing file without it.
+fn strip_indicator(line: String) -> String {
+    match line.chars().next_back() {
+        Some('*') | Some('@') | Some('|') | Some('=') | Some('/')
+            if fs::symlink_metadata(&line).is_err()
+                && fs::symlink_metadata(&line[..line.len() - 1]).is_ok() =>
+        {
+            line[..line.len() - 1].to_owned()
+        }
+        _ => line,
+    }
+}
+
+fn source_files(quiet: bool) -> io::Result<Vec<PathBuf>> {
+    let mut stripped = false;
+    let files = io::stdin()
         .lock()
-        .lines()
-        .map(|line| line.map(From::from))
-        .collect()
+        .split(b'\n')
+        .map(|line| {
+            line.map(|mut line| {
+                if line.ends_with(b"\r") {
+                    line.pop();
+                }
+                // Names in other encodings than UTF-8 are taken as they are.
+                let line = match String::from_utf8(line) {
+                    Ok(line) => line,
+                    Err(err) => return PathBuf::from(ffi::OsString::from_vec(err.into_bytes())),
+                };
+                let line = if line.contains('\x1b') {
+                    stripped = true;
+                    strip_escapes(&line)
+                } else {
+                    line
+                };
+                strip_indicator(line).into()
+            })
+        })
+        .collect();
+    if stripped && !quiet {
+        eprintln!("bulkrename: removed terminal escape sequences from input");
+    }
+    files
 }
 
-fn destination_files<P>(temp_path: P) -> io::Result<Vec<PathBuf>>
+/// Start of the comment lines added to the editor buffer, which are ignored
+/// when reading names back.
+const HEADER: &str = "# ── ";
+
+/// Reorders the indices of `paths` in `order` so that files in the same
+/// directory are next to each other. Directories appear in the order of their
+/// first file.
+fn group_order<P>(paths: &[P], order: Vec<usize>) -> Vec<usize>
 where
     P: AsRef<Path>,
 {
-    io::BufReader::new(fs::File::open(temp_path)?)
-        .lines()
-        .filter(|line| line.as_ref().map(|line| !line.is_empty()).unwrap_or(true))
-        .map(|line| line.map(From::from))
+    let mut groups: Vec<(&Path, Vec<usize>)> = vec![];
+    for index in order {
+        let dir = parent_dir(paths[index].as_ref());
+        match groups.iter_mut().find(|(group_dir, _)| *group_dir == dir) {
+            Some((_, indices)) => indices.push(index),
+            None => groups.push((dir, vec![index])),
+        }
+    }
+    groups
+        .into_iter()
+        .flat_map(|(_, indices)| indices)
         .collect()
 }
 
-fn spawn_editor<P>(path: P) -> Result<(), Error>
+/// Returns the deepest directory containing all of `paths`, compared by
+/// components, or `None` if they have no common directory.
+fn common_prefix<P>(paths: &[P]) -> Option<PathBuf>
 where
     P: AsRef<Path>,
 {
-    let editor = std::env::var("EDITOR").unwrap_or_else(|_| "vi".into());
-    let mut command = process::Command::new(editor);
-    command.arg(path.as_ref());
-    if unsafe { libc::isatty(io::stdin().lock().as_raw_fd()) == 0 } {
-        command.stdin(fs::File::open("/dev/tty")?);
+    let mut paths = paths.iter().map(|path| path.as_ref().parent());
+    let mut prefix: Vec<Component> = paths.next()??.components().collect();
+    for parent in paths {
+        let common = parent?
+            .components()
+            .zip(&prefix)
+            .take_while(|(a, b)| a == *b)
+            .count();
+        prefix.truncate(common);
     }
-    if command.status()?.success() {
-        Ok(())
+    if prefix.is_empty() {
+        None
     } else {
-        Err(Error::Editor)
+        Some(prefix.into_iter().collect())
     }
 }
 
-fn write_lines<L, T>(destination: &Path, lines: &mut L) -> io::Result<()>
-where
-    L: Iterator<Item = T>,
-    T: AsRef<[u8]>,
-{
-    let mut writer = io::BufWriter::new(fs::OpenOptions::new().write(true).open(destination)?);
-    lines.try_for_each(|line| {
-        writer
-            .write_all(line.as_ref())
-            .and_then(|_| writer.write_all(&[b'\n']))
-    })
+/// Reads the names from the edited buffer together with their line numbers.
+/// Windows line endings and a byte order mark, which some editors add, are
+/// removed.
+fn destination_files(
+    lines: Vec<Vec<u8>>,
+    encoding: Encoding,
+    quiet: bool,
+) -> Result<Vec<(usize, PathBuf)>, Error> {
+    let mut converted = false;
+    let mut files = vec![];
+    for (index, mut line) in lines.into_iter().enumerate() {
+        if index == 0 && line.starts_with("\u{feff}".as_bytes()) {
+            line.drain(..3);
+            converted = true;
+        }
+        if line.ends_with(b"\r") {
+            line.pop();
+            converted = true;
+        }
+        if !line.is_empty() && !line.starts_with(HEADER.as_bytes()) {
+            let name = encoding
+                .encode(&line)
+                .map_err(|err| err.at_line(index + 1))?;
+            files.push((index + 1, PathBuf::from(ffi::OsString::from_vec(name))));
+        }
+    }
+    if converted && !quiet {
+        eprintln!(
+            "bulkrename: removed Windows line endings or a byte order mark from the file list"
+        );
+    }
+    Ok(files)
 }
 
 #[inline]
@@ -183,64 +1359,2133 @@ where
     }
 }
 
-fn bulk_rename<P>(source_files: &[P], replace: bool, quiet: bool) -> Result<(), Error>
+/// Exchanges the names of `a` and `b` atomically where the file system
+/// supports it, or through a temporary name otherwise.
+fn exchange(a: &Path, b: &Path) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        let a = ffi::CString::new(path_as_bytes(&a))?;
+        let b = ffi::CString::new(path_as_bytes(&b))?;
+        let result = unsafe {
+            renameat2(
+                libc::AT_FDCWD,
+                a.as_ptr(),
+                libc::AT_FDCWD,
+                b.as_ptr(),
+                libc::RENAME_EXCHANGE as raw::c_uint,
+            )
+        };
+        if result == 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        match err.raw_os_error() {
+            Some(libc::EINVAL) | Some(libc::ENOSYS) => {}
+            _ => return Err(err),
+        }
+    }
+    let staging = copy::staging_path(a);
+    rename(a, &staging, false)?;
+    if let Err(err) = rename(b, a, false) {
+        rename(&staging, a, false)?;
+        return Err(err);
+    }
+    if let Err(err) = rename(&staging, b, false) {
+        rename(a, b, false)?;
+        rename(&staging, a, false)?;
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Device and inode numbers identifying a file.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct Identity {
+    dev: u64,
+    ino: u64,
+}
+
+impl Identity {
+    fn of<P>(path: P) -> Option<Self>
+    where
+        P: AsRef<Path>,
+    {
+        fs::symlink_metadata(path).ok().map(|metadata| Identity {
+            dev: metadata.dev(),
+            ino: metadata.ino(),
+        })
+    }
+}
+
+/// The state of a file when the file list was generated, used to detect
+/// files that changed while the list was being edited.
+#[derive(PartialEq)]
+struct Snapshot {
+    identity: Identity,
+    mtime: (i64, i64),
+}
+
+impl Snapshot {
+    fn of<P>(path: P) -> Option<Self>
+    where
+        P: AsRef<Path>,
+    {
+        fs::symlink_metadata(path).ok().map(|metadata| Snapshot {
+            identity: Identity {
+                dev: metadata.dev(),
+                ino: metadata.ino(),
+            },
+            mtime: (metadata.mtime(), metadata.mtime_nsec()),
+        })
+    }
+}
+
+fn changed_error(path: &Path) -> io::Error {
+    io::Error::other(format!(
+        "{} was replaced while the file list was being edited",
+        path.quoted()
+    ))
+}
+
+/// Renames `source` relative to descriptors of the parent directories,
+/// refusing to do so if `source` no longer refers to the file `identity`
+/// was taken from.
+#[cfg(target_os = "linux")]
+fn rename_verified(
+    source: &Path,
+    dest: &Path,
+    replace: bool,
+    identity: Identity,
+) -> io::Result<()> {
+    let (source_dir, source_name) = dirs::open_parent(source)?;
+    let (dest_dir, dest_name) = dirs::open_parent(dest)?;
+    let mut stat = mem::MaybeUninit::<libc::stat>::zeroed();
+    if unsafe {
+        libc::fstatat(
+            source_dir.as_raw_fd(),
+            source_name.as_ptr(),
+            stat.as_mut_ptr(),
+            libc::AT_SYMLINK_NOFOLLOW,
+        )
+    } == -1
+    {
+        return Err(io::Error::last_os_error());
+    }
+    let stat = unsafe { stat.assume_init() };
+    if stat.st_dev != identity.dev || stat.st_ino != identity.ino {
+        return Err(changed_error(source));
+    }
+    let flags: raw::c_uint = if replace {
+        0
+    } else {
+        libc::RENAME_NOREPLACE as raw::c_uint
+    };
+    if unsafe {
+        renameat2(
+            source_dir.as_raw_fd(),
+            source_name.as_ptr(),
+            dest_dir.as_raw_fd(),
+            dest_name.as_ptr(),
+            flags,
+        )
+    } == -1
+    {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn rename_verified(
+    source: &Path,
+    dest: &Path,
+    replace: bool,
+    identity: Identity,
+) -> io::Result<()> {
+    if Identity::of(source) != Some(identity) {
+        return Err(changed_error(source));
+    }
+    rename(source, dest, replace)
+}
+
+fn is_dir<P>(path: P) -> bool
 where
     P: AsRef<Path>,
 {
-    let temp = NamedTempFile::new()?;
-    write_lines(
-        temp.path(),
-        &mut source_files.iter().map(|path| path_as_bytes(path)),
-    )?;
-    spawn_editor(temp.path())?;
-    let destination_files = destination_files(temp.path())?;
-    if destination_files.len() != source_files.len() {
-        return Err(Error::InvalidFileList);
-    }
-    let mut count = 0;
-    source_files
-        .iter()
-        .zip(destination_files.iter())
-        .try_for_each(|(source, destination)| -> Result<(), Error> {
-            if source.as_ref() != destination {
-                rename(source, destination, replace)?;
-                if !quiet {
-                    println!(
-                        "renaming {} to {}",
-                        source.as_ref().to_string_lossy(),
-                        destination.to_string_lossy()
-                    );
+    fs::symlink_metadata(path)
+        .map(|metadata| metadata.is_dir())
+        .unwrap_or(false)
+}
+
+/// Whether `path` is a symbolic link to a file that does not exist.
+fn is_dangling(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_symlink())
+        && fs::metadata(path).is_err_and(|err| err.kind() == io::ErrorKind::NotFound)
+}
+
+/// Lexically normalizes `path` by dropping `.` components and resolving `..`
+/// against preceding components without consulting the file system.
+fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match result.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    result.pop();
                 }
-                count += 1;
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                _ => result.push(".."),
+            },
+            component => result.push(component),
+        }
+    }
+    result
+}
+
+/// Returns true if `a` and `b` differ only by `.` components and repeated
+/// or trailing slashes, such as `./photo.jpg` and `photo.jpg`. Paths with
+/// `..` components are compared as they are, since symbolic links can make
+/// `dir/..` a different directory than the one containing `dir`.
+fn same_path(a: &Path, b: &Path) -> bool {
+    let not_current = |component: &Component| *component != Component::CurDir;
+    a.components()
+        .filter(not_current)
+        .eq(b.components().filter(not_current))
+}
+
+/// Expands a leading `~` to the home directory and `$VAR` and `${VAR}` to the
+/// values of environment variables.
+fn expand(path: &Path) -> Result<PathBuf, Error> {
+    let text = match path.to_str() {
+        Some(text) => text,
+        None => return Ok(path.to_owned()),
+    };
+    let mut result = String::new();
+    let mut rest = text;
+    if rest == "~" || rest.starts_with("~/") {
+        let home = env::var("HOME").map_err(|_| Error::UndefinedVariable("HOME".into()))?;
+        result.push_str(&home);
+        rest = &rest[1..];
+    }
+    while let Some(index) = rest.find('$') {
+        result.push_str(&rest[..index]);
+        rest = &rest[index + 1..];
+        let (name, remaining) = if rest.starts_with('{') {
+            match rest.find('}') {
+                Some(end) => (&rest[1..end], &rest[end + 1..]),
+                None => ("", rest),
             }
-            Ok(())
-        })?;
-    if !quiet {
-        println!("{} files renamed", count);
+        } else {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            (&rest[..end], &rest[end..])
+        };
+        if name.is_empty() {
+            result.push('$');
+            continue;
+        }
+        let value = env::var(name).map_err(|_| Error::UndefinedVariable(name.into()))?;
+        result.push_str(&value);
+        rest = remaining;
     }
-    Ok(())
+    result.push_str(rest);
+    Ok(result.into())
 }
 
-fn run() -> Result<(), Error> {
-    let args = Args::parse()?;
-    if args.show_help {
-        print!("{}", USAGE);
+/// Refuses paths whose renaming would most likely be a mistake: the root
+/// directory, `.` and `..`, and anything inside protected locations.
+fn check_path(path: &Path, args: &Args) -> Result<(), Error> {
+    if args.allow_system {
         return Ok(());
     }
-    let source_files = if args.files.is_empty() {
-        source_files()?
-    } else {
-        args.files
+    let reason = match path.components().next_back() {
+        Some(Component::CurDir) | Some(Component::ParentDir) => Some("current or parent directory"),
+        Some(Component::RootDir) | None => Some("root directory"),
+        _ => {
+            let cwd = env::current_dir().with_path(Operation::Inspect, Path::new("."))?;
+            let absolute = normalize(&cwd.join(path));
+            if absolute.parent().is_none() {
+                Some("root directory")
+            } else if args.protected.iter().any(|dir| absolute.starts_with(dir)) {
+                Some("protected location")
+            } else {
+                None
+            }
+        }
     };
-    if source_files.is_empty() {
-        return Ok(());
+    match reason {
+        Some(reason) => Err(Error::DangerousPath(path.to_owned(), reason)),
+        None => Ok(()),
     }
-    bulk_rename(source_files.as_ref(), args.replace, args.quiet)
 }
 
-fn main() {
-    if let Err(err) = run() {
-        eprintln!("bulkrename: {}", err);
-        process::exit(1);
+/// Creates `dir` and its missing parents. With `mode`, the new directories
+/// get exactly that mode, including the setgid and sticky bits, regardless
+/// of the umask.
+fn create_dirs(dir: &Path, mode: Option<u32>) -> io::Result<()> {
+    let mode = match mode {
+        Some(mode) => mode,
+        None => return fs::create_dir_all(dir),
+    };
+    let mut missing = vec![];
+    let mut current = dir;
+    while fs::symlink_metadata(current).is_err() {
+        missing.push(current);
+        match current.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => current = parent,
+            _ => break,
+        }
+    }
+    for dir in missing.into_iter().rev() {
+        match fs::create_dir(dir) {
+            Ok(()) => fs::set_permissions(dir, fs::Permissions::from_mode(mode))?,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+/// Refuses destinations containing control characters that the source does
+/// not contain, which are usually accidents of editing.
+fn check_control_chars(source: &Path, destination: &Path) -> Result<(), Error> {
+    let controls = |path: &Path| -> HashSet<char> {
+        String::from_utf8_lossy(path_as_bytes(&path))
+            .chars()
+            .filter(|c| c.is_control())
+            .collect()
+    };
+    if controls(destination).is_subset(&controls(source)) {
+        Ok(())
+    } else {
+        Err(Error::ControlCharacters(destination.to_owned()))
+    }
+}
+
+/// Refuses destinations whose names exceed the limits of the file system
+/// they are placed on. `limits` caches the limits of each directory. If
+/// `parents` is true, missing directories are assumed to be on the file system
+/// of their closest existing ancestor.
+fn check_length(
+    path: &Path,
+    limits: &mut HashMap<PathBuf, filesystem::NameLimits>,
+    parents: bool,
+) -> Result<(), Error> {
+    let mut dir = parent_dir(path);
+    if parents {
+        while fs::symlink_metadata(dir).is_err() && dir != Path::new(".") {
+            match dir.parent() {
+                Some(parent) => dir = parent_dir(parent),
+                None => break,
+            }
+        }
+    }
+    let dir_limits = match limits.get(dir) {
+        Some(dir_limits) => *dir_limits,
+        None => {
+            let dir_limits = filesystem::name_limits(dir).with_path(Operation::Inspect, dir)?;
+            limits.insert(dir.to_owned(), dir_limits);
+            dir_limits
+        }
+    };
+    let name_len = path.file_name().map_or(0, |name| name.len());
+    match dir_limits.name_max {
+        Some(name_max) if name_len > name_max => {
+            return Err(Error::NameTooLong(path.to_owned(), name_max))
+        }
+        _ => {}
+    }
+    // The limit includes the terminating null byte.
+    match dir_limits.path_max {
+        Some(path_max) if path.as_os_str().len() >= path_max => {
+            Err(Error::PathTooLong(path.to_owned(), path_max))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Refuses destinations that would collide on case-insensitive file systems
+/// because they differ from each other only by case.
+fn check_case_collisions<'a, I>(destinations: I) -> Result<(), Error>
+where
+    I: IntoIterator<Item = (usize, &'a Path)>,
+{
+    let cwd = env::current_dir().with_path(Operation::Inspect, Path::new("."))?;
+    let mut case_insensitive = HashMap::new();
+    let mut seen: HashMap<(PathBuf, String), &Path> = HashMap::new();
+    for (line, destination) in destinations {
+        let dir = normalize(&cwd.join(parent_dir(destination)));
+        let insensitive = match case_insensitive.get(&dir) {
+            Some(insensitive) => *insensitive,
+            None => {
+                // Missing directories are reported when renaming.
+                let insensitive = filesystem::is_case_insensitive(&dir).unwrap_or(false);
+                case_insensitive.insert(dir.clone(), insensitive);
+                insensitive
+            }
+        };
+        if !insensitive {
+            continue;
+        }
+        let name = destination
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_lowercase();
+        if let Some(other) = seen.insert((dir, name), destination) {
+            if other != destination {
+                return Err(
+                    Error::CaseCollision(other.to_owned(), destination.to_owned()).at_line(line),
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns `path` with its extension replaced by the extension of `original`,
+/// or removed if `original` has none.
+fn with_extension_of(path: &Path, original: &Path) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_owned();
+    if let Some(extension) = original.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name)
+}
+
+/// Returns the location for a backup of `path` inside `dir`. Paths below the
+/// working directory keep their relative layout, others their absolute one.
+fn backup_dir_path(dir: &Path, path: &Path) -> io::Result<PathBuf> {
+    let cwd = env::current_dir()?;
+    let absolute = normalize(&cwd.join(path));
+    let relative = absolute
+        .strip_prefix(&cwd)
+        .or_else(|_| absolute.strip_prefix("/"))
+        .unwrap_or(&absolute);
+    Ok(dir.join(relative))
+}
+
+/// Moves an existing `dest` out of the way according to the backup scheme and
+/// returns the path it was moved to.
+fn back_up(dest: &Path, backup: &Backup) -> io::Result<Option<PathBuf>> {
+    if fs::symlink_metadata(dest).is_err() {
+        return Ok(None);
+    }
+    let name = dest.as_os_str();
+    match backup {
+        Backup::None => Ok(None),
+        Backup::Trash => trash::trash(dest).map(Some),
+        Backup::Suffix(suffix) => {
+            let mut path = name.to_owned();
+            path.push(suffix);
+            rename(dest, &path, true)?;
+            Ok(Some(path.into()))
+        }
+        Backup::Directory(dir) => {
+            let path = backup_dir_path(dir, dest)?;
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            match rename(dest, &path, false) {
+                Ok(()) => Ok(Some(path)),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    back_up_numbered(dest, path.as_os_str())
+                }
+                Err(err) => Err(err),
+            }
+        }
+        Backup::Numbered => back_up_numbered(dest, name),
+    }
+}
+
+/// Moves `backup`, made by `back_up`, back to `dest`.
+fn restore_backup(backup: &Path, dest: &Path, scheme: &Backup) -> io::Result<()> {
+    match scheme {
+        Backup::Trash => trash::restore(backup, dest),
+        _ => rename(backup, dest, false),
+    }
+}
+
+fn back_up_numbered(dest: &Path, name: &ffi::OsStr) -> io::Result<Option<PathBuf>> {
+    for n in 1.. {
+        let mut path = name.to_owned();
+        path.push(format!("~{}~", n));
+        match rename(dest, &path, false) {
+            Ok(()) => return Ok(Some(path.into())),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    unreachable!()
+}
+
+fn is_transient(err: &io::Error) -> bool {
+    matches!(
+        err.raw_os_error(),
+        Some(libc::EBUSY) | Some(libc::ESTALE) | Some(libc::EAGAIN)
+    )
+}
+
+/// Runs `operation`, retrying it with exponential backoff while it fails with
+/// errors that network file systems are known to report intermittently.
+fn retry<F>(retries: u32, quiet: bool, mut operation: F) -> io::Result<()>
+where
+    F: FnMut() -> io::Result<()>,
+{
+    let mut delay = Duration::from_millis(100);
+    for _ in 0..retries {
+        match operation() {
+            Err(err) if is_transient(&err) => {
+                if !quiet {
+                    eprintln!("bulkrename: {}, retrying in {:?}", err, delay);
+                }
+                thread::sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    operation()
+}
+
+fn move_file(
+    source: &Path,
+    dest: &Path,
+    replace: bool,
+    identity: Option<Identity>,
+    args: &Args,
+) -> io::Result<()> {
+    // Checked before the destination is backed up, and again when renaming
+    // where that can be done atomically.
+    if identity.is_some() && Identity::of(source) != identity {
+        return Err(changed_error(source));
+    }
+    let backup = match replace {
+        true => back_up(dest, &args.backup)?,
+        false => None,
+    };
+    if let Some(backup) = &backup {
+        #[cfg(target_os = "linux")]
+        dirs::forget(dest);
+        if !args.quiet {
+            println!("backing up {} to {}", dest.quoted(), backup.quoted());
+        }
+    }
+    let result = match vcs::tracking(&args.vcs, source) {
+        Some(vcs) => vcs.rename(source, dest, replace),
+        None => {
+            let result = retry(args.retries, args.quiet, || match identity {
+                Some(identity) => rename_verified(source, dest, replace, identity),
+                None => rename(source, dest, replace),
+            });
+            match result {
+                Err(err) if err.raw_os_error() == Some(libc::EXDEV) => {
+                    copy::move_across(source, dest, replace, &args.copy)
+                }
+                result => result,
+            }
+        }
+    };
+    if let (Err(_), Some(backup)) = (&result, &backup) {
+        if let Err(err) = restore_backup(backup, dest, &args.backup) {
+            eprintln!(
+                "bulkrename: cannot restore {} from {}: {}",
+                dest.quoted(),
+                backup.quoted(),
+                err
+            );
+        } else if !args.quiet {
+            println!("restoring {} from {}", dest.quoted(), backup.quoted());
+        }
+    }
+    // Whatever was at the source and, if it was replaced, at the destination
+    // is no longer there.
+    #[cfg(target_os = "linux")]
+    if result.is_ok() {
+        dirs::forget(source);
+        if replace {
+            dirs::forget(dest);
+        }
+    }
+    result
+}
+
+/// Returns `path` with `n` formatted according to `format` inserted between
+/// the file stem and the extension.
+fn numbered(path: &Path, format: &str, n: usize) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_owned();
+    name.push(format.replace("{}", &n.to_string()));
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name)
+}
+
+/// Moves `source` to `dest`, numbering the destination or consulting
+/// `resolver` if it already exists. Returns the final destination or `None` if the file was
+/// skipped.
+fn move_interactively(
+    source: &Path,
+    dest: &Path,
+    identity: Option<Identity>,
+    resolver: &mut conflict::Resolver,
+    args: &Args,
+) -> Result<Option<PathBuf>, Error> {
+    let mut dest = dest.to_owned();
+    let mut replace = args.replace;
+    if let (true, Some(condition), Ok(existing)) =
+        (replace, args.replace_if, fs::symlink_metadata(&dest))
+    {
+        let metadata = fs::symlink_metadata(source).with_path(Operation::Inspect, source)?;
+        if !condition.allows(&metadata, &existing) {
+            if !args.quiet {
+                println!(
+                    "keeping {}, {} is not {}",
+                    dest.quoted(),
+                    source.quoted(),
+                    condition.name()
+                );
+            }
+            return Ok(None);
+        }
+    }
+    let mut n = 0;
+    loop {
+        let candidate = if n > 0 {
+            numbered(&dest, &args.number_format, n)
+        } else {
+            dest.clone()
+        };
+        match move_file(source, &candidate, replace, identity, args) {
+            Ok(()) => {
+                if n > 0 && !args.quiet {
+                    println!(
+                        "{} already exists, using {}",
+                        dest.quoted(),
+                        candidate.quoted()
+                    );
+                }
+                return Ok(Some(candidate));
+            }
+            Err(err)
+                if args.if_identical.is_some()
+                    && err.kind() == io::ErrorKind::AlreadyExists
+                    && copy::same_contents(source, &candidate).with_paths(
+                        Operation::Compare,
+                        source,
+                        &candidate,
+                    )? =>
+            {
+                if args.if_identical == Some(Identical::Skip) {
+                    return Ok(None);
+                }
+                fs::remove_file(source).with_path(Operation::Remove, source)?;
+                if !args.quiet {
+                    println!(
+                        "removing {}, {} has the same contents",
+                        source.quoted(),
+                        candidate.quoted()
+                    );
+                }
+                return Ok(Some(candidate));
+            }
+            Err(err) if args.auto_number && err.kind() == io::ErrorKind::AlreadyExists => n += 1,
+            Err(err) if args.interactive && err.kind() == io::ErrorKind::AlreadyExists => {
+                match resolver.resolve(&dest)? {
+                    conflict::Resolution::Overwrite => replace = true,
+                    conflict::Resolution::Skip => return Ok(None),
+                    conflict::Resolution::Rename(path) => dest = path,
+                }
+            }
+            Err(err) => return Err(err).with_paths(Operation::Rename, source, &candidate),
+        }
+    }
+}
+
+fn merge_entries(source: &Path, dest: &Path, args: &Args) -> Result<usize, Error> {
+    let mut conflicts = 0;
+    for entry in fs::read_dir(source).with_path(Operation::List, source)? {
+        let entry = entry.with_path(Operation::List, source)?;
+        let source = entry.path();
+        let dest = dest.join(entry.file_name());
+        if is_dir(&source) && is_dir(&dest) {
+            let nested = merge_entries(&source, &dest, args)?;
+            if nested == 0 {
+                fs::remove_dir(&source).with_path(Operation::Remove, &source)?;
+            }
+            conflicts += nested;
+            continue;
+        }
+        match move_file(&source, &dest, args.replace, None, args) {
+            Ok(()) => {
+                if !args.quiet {
+                    println!("  moving {} to {}", source.quoted(), dest.quoted());
+                }
+            }
+            Err(err) if fs::symlink_metadata(&dest).is_ok() => {
+                eprintln!(
+                    "bulkrename: cannot merge {} into {}: {}",
+                    source.quoted(),
+                    dest.quoted(),
+                    err
+                );
+                conflicts += 1;
+            }
+            Err(err) => return Err(err).with_paths(Operation::Rename, &source, &dest),
+        }
+    }
+    Ok(conflicts)
+}
+
+/// Moves the contents of `source` into the existing directory `dest`,
+/// descending into subdirectories that exist on both sides. Entries that
+/// cannot be moved because of a conflict are reported and left in place.
+fn merge_dirs(source: &Path, dest: &Path, args: &Args) -> Result<(), Error> {
+    let conflicts = merge_entries(source, dest, args)?;
+    if conflicts > 0 {
+        return Err(Error::MergeConflict(source.to_owned(), conflicts));
+    }
+    fs::remove_dir(source).with_path(Operation::Remove, source)?;
+    Ok(())
+}
+
+/// Returns the canonical absolute form of `path`. Unlike `fs::canonicalize`,
+/// this does not resolve `path` itself if it is a symbolic link.
+fn absolute(path: &Path) -> io::Result<PathBuf> {
+    match path.file_name() {
+        Some(name) => Ok(parent_dir(path).canonicalize()?.join(name)),
+        None => path.canonicalize(),
+    }
+}
+
+/// Returns whether `path` consists of a file name only.
+fn is_bare_name(path: &Path) -> bool {
+    let mut components = path.components();
+    matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    )
+}
+
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+/// Locks the directories containing `paths` that exist.
+fn lock_dirs<'a, I>(locks: &mut lock::Locks, paths: I) -> Result<(), Error>
+where
+    I: IntoIterator<Item = &'a Path>,
+{
+    for path in paths {
+        let dir = parent_dir(path);
+        match locks.lock(dir) {
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                return Err(Error::Locked(dir.to_owned()))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            result => result.with_path(Operation::Lock, dir)?,
+        }
+    }
+    Ok(())
+}
+
+/// Flushes the directory entries of `dir` to disk.
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    fs::File::open(dir)?.sync_all()
+}
+
+/// Flushes the directories containing the sources and destinations of
+/// `renames` to disk.
+fn sync_renames(renames: &[(PathBuf, PathBuf)]) -> Result<(), Error> {
+    let dirs: BTreeSet<_> = renames
+        .iter()
+        .flat_map(|(source, destination)| vec![parent_dir(source), parent_dir(destination)])
+        .collect();
+    for dir in dirs {
+        sync_dir(dir).with_path(Operation::Sync, dir)?;
+    }
+    Ok(())
+}
+
+/// Limits file system modifications to the directories involved in renaming
+/// `source_files` to `destination_files`.
+#[cfg(target_os = "linux")]
+fn enter_sandbox<P>(
+    source_files: &[P],
+    destination_files: &[PathBuf],
+    args: &Args,
+) -> io::Result<()>
+where
+    P: AsRef<Path>,
+{
+    let mut directories: BTreeSet<PathBuf> = source_files
+        .iter()
+        .map(|path| parent_dir(path.as_ref()))
+        .chain(destination_files.iter().map(|path| parent_dir(path)))
+        .map(From::from)
+        .collect();
+    directories.extend(args.symlink_dirs.iter().cloned());
+    directories.extend(args.hardlink_root.iter().cloned());
+    directories.extend(args.report.iter().map(|path| parent_dir(path).to_owned()));
+    let metadata_dirs = args
+        .vcs
+        .iter()
+        .flat_map(|vcs| {
+            directories
+                .iter()
+                .filter_map(move |dir| vcs.metadata_dir(dir))
+        })
+        .collect::<Vec<_>>();
+    directories.extend(metadata_dirs);
+    // Interactive conflict resolution may replace files as well.
+    if args.replace || args.interactive {
+        match &args.backup {
+            Backup::Trash => {
+                let trashes = destination_files
+                    .iter()
+                    .filter_map(|path| trash::directory(parent_dir(path)).ok())
+                    .collect::<Vec<_>>();
+                directories.extend(trashes);
+            }
+            Backup::Directory(dir) => {
+                fs::create_dir_all(dir)?;
+                directories.insert(dir.clone());
+            }
+            Backup::None | Backup::Numbered | Backup::Suffix(_) => {}
+        }
+    }
+    sandbox::restrict(directories.iter().map(AsRef::as_ref))?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn enter_sandbox<P>(_: &[P], _: &[PathBuf], _: &Args) -> io::Result<()> {
+    Ok(())
+}
+
+/// Renames a single entry of the plan, returning the final destination or
+/// `None` if the entry was skipped.
+fn rename_entry(
+    source: &Path,
+    destination: &Path,
+    identity: Option<Identity>,
+    resolver: &mut conflict::Resolver,
+    args: &Args,
+) -> Result<Option<PathBuf>, Error> {
+    if args.merge && is_dir(source) && is_dir(destination) {
+        if !args.quiet {
+            println!("merging {} into {}", source.quoted(), destination.quoted());
+        }
+        merge_dirs(source, destination, args)?;
+        return Ok(Some(destination.to_owned()));
+    }
+    let result = move_interactively(source, destination, identity, resolver, args)?;
+    if !args.quiet {
+        match &result {
+            Some(destination) => {
+                println!("renaming {} to {}", source.quoted(), destination.quoted())
+            }
+            None => println!("skipping {}", source.quoted()),
+        }
+    }
+    Ok(result)
+}
+
+/// Applies the attribute changes made in the editor to `path`.
+fn change_attributes(path: &Path, changes: &AttributeChanges, args: &Args) -> Result<(), Error> {
+    for (column, value) in changes {
+        column.apply(path, value).map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!(
+                    "cannot change {} of {}: {}",
+                    column.name(),
+                    path.quoted(),
+                    err
+                ),
+            )
+        })?;
+        if !args.quiet {
+            println!(
+                "changing {} of {} to {}",
+                column.name(),
+                path.quoted(),
+                value
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Gives the hard links in `siblings` that share the file name of `source`
+/// the file name of `destination`. Links that are renamed as part of the
+/// plan, listed in `sources`, are left alone.
+fn rename_hardlinks(
+    source: &Path,
+    destination: &Path,
+    siblings: &[PathBuf],
+    sources: &HashSet<PathBuf>,
+    args: &Args,
+) -> Vec<(PathBuf, PathBuf)> {
+    let mut renames = vec![];
+    let name = match destination.file_name() {
+        Some(name) => name,
+        None => return renames,
+    };
+    for sibling in siblings {
+        let listed = symlinks::absolute_lexical(sibling)
+            .map(|path| sources.contains(&path))
+            .unwrap_or(false);
+        if listed {
+            continue;
+        }
+        if sibling.file_name() != source.file_name() {
+            eprintln!(
+                "bulkrename: warning: not renaming hard link {} with a different name",
+                sibling.quoted()
+            );
+            continue;
+        }
+        let target = sibling.with_file_name(name);
+        match rename(sibling, &target, false) {
+            Ok(()) => {
+                if !args.quiet {
+                    println!(
+                        "renaming hard link {} to {}",
+                        sibling.quoted(),
+                        target.quoted()
+                    );
+                }
+                renames.push((sibling.clone(), target));
+            }
+            Err(err) => eprintln!(
+                "bulkrename: failed to rename hard link {}: {}",
+                sibling.quoted(),
+                err
+            ),
+        }
+    }
+    renames
+}
+
+/// Attribute changes made in the columns of the editor buffer.
+type AttributeChanges = Vec<(columns::Column, String)>;
+
+/// Prints where lines seem to have been added to or removed from the edited
+/// buffer. Groups of changed lines where as many lines were removed as added
+/// are taken to be renames and not shown.
+fn report_line_count(original: &[PathBuf], edited: &[(usize, PathBuf)]) {
+    /// Most groups of lines shown.
+    const MAX_HUNKS: usize = 10;
+    let edited_names: Vec<_> = edited.iter().map(|(_, name)| name).collect();
+    let original: Vec<_> = original.iter().collect();
+    let edits = match diff::diff(&original, &edited_names, 1000) {
+        Some(edits) => edits,
+        None => return,
+    };
+    let (mut i, mut j) = (0, 0);
+    let mut deleted = vec![];
+    let mut inserted = vec![];
+    let mut hunks = 0;
+    for edit in edits.into_iter().chain(Some(diff::Edit::Keep)) {
+        match edit {
+            diff::Edit::Delete => {
+                deleted.push(original[i]);
+                i += 1;
+                continue;
+            }
+            diff::Edit::Insert => {
+                inserted.push(&edited[j]);
+                j += 1;
+                continue;
+            }
+            diff::Edit::Keep => {}
+        }
+        if deleted.len() != inserted.len() && hunks < MAX_HUNKS {
+            let line = match (inserted.first(), edited.get(j)) {
+                (Some((line, _)), _) | (None, Some((line, _))) => *line,
+                (None, None) => edited.last().map(|(line, _)| line + 1).unwrap_or(1),
+            };
+            eprintln!("bulkrename: around line {} of the edited file list:", line);
+            for name in &deleted {
+                eprintln!("  - {}", name.quoted());
+            }
+            for (_, name) in &inserted {
+                eprintln!("  + {}", name.quoted());
+            }
+            hunks += 1;
+        }
+        deleted.clear();
+        inserted.clear();
+        i += 1;
+        j += 1;
+    }
+}
+
+/// New names, attribute changes and buffer line numbers of edited files.
+type Edited = (Vec<PathBuf>, Vec<AttributeChanges>, Vec<usize>);
+
+/// Lets the user edit the names of `source_files`, and the attributes in
+/// `args.columns`, in an editor. Returns the new names, the changed
+/// attributes and the line of the buffer of each file.
+fn edit<P>(source_files: &[P], args: &Args) -> Result<Edited, Error>
+where
+    P: AsRef<Path>,
+{
+    let attributes = source_files
+        .iter()
+        .map(|path| {
+            args.columns
+                .iter()
+                .map(|column| column.read(path.as_ref()))
+                .collect::<io::Result<_>>()
+                .with_path(Operation::Inspect, path.as_ref())
+        })
+        .collect::<Result<Vec<Vec<_>>, _>>()?;
+    let mut order: Vec<usize> = (0..source_files.len()).collect();
+    match args.sort {
+        Some(Sort::Bytes) => order.sort_by_key(|&index| path_as_bytes(&source_files[index])),
+        Some(Sort::Locale) => {
+            let keys: Vec<_> = source_files
+                .iter()
+                .map(|path| collate::sort_key(path_as_bytes(path)))
+                .collect();
+            order.sort_by(|&a, &b| keys[a].cmp(&keys[b]));
+        }
+        None => {}
+    }
+    if args.group {
+        order = group_order(source_files, order);
+    }
+    let prefix = if args.trim_prefix {
+        common_prefix(source_files)
+    } else {
+        None
+    };
+    let mut lines = vec![];
+    let mut names = vec![];
+    // The names shown for each file, without attribute columns.
+    let mut shown = vec![PathBuf::new(); source_files.len()];
+    if let Some(prefix) = &prefix {
+        let header = format!("{}{}/ ──", HEADER, prefix.quoted());
+        lines.push(header.into_bytes());
+    }
+    let mut proposed: Vec<_> = source_files
+        .iter()
+        .map(|path| {
+            let template = args.template.as_ref()?;
+            rules::expand(template, path.as_ref())
+        })
+        .collect();
+    if let Some(program) = &args.plugin {
+        let paths: Vec<_> = source_files
+            .iter()
+            .zip(&proposed)
+            .map(|(path, proposed)| proposed.as_deref().unwrap_or_else(|| path.as_ref()))
+            .collect();
+        proposed = plugin::run(program, &paths)?
+            .into_iter()
+            .map(Some)
+            .collect();
+    }
+    let mut current_dir = None;
+    for &index in &order {
+        let path = source_files[index].as_ref();
+        let path = match &prefix {
+            Some(prefix) => path.strip_prefix(prefix).unwrap_or(path),
+            None => path,
+        };
+        if args.group && current_dir != Some(parent_dir(path)) {
+            current_dir = Some(parent_dir(path));
+            let header = format!("{}{} ──", HEADER, parent_dir(path).quoted());
+            lines.push(header.into_bytes());
+        }
+        let name = match (proposed[index].clone(), &prefix) {
+            (Some(templated), Some(prefix)) => match templated.strip_prefix(prefix) {
+                Ok(name) => name.to_owned(),
+                Err(_) => templated,
+            },
+            (Some(templated), None) => templated,
+            (None, _) => path.to_owned(),
+        };
+        let name = transform::apply(&args.transforms, args.transform_scope, &name);
+        let line = columns::join(&attributes[index], path_as_bytes(&name));
+        names.push(PathBuf::from(ffi::OsStr::from_bytes(&line)));
+        lines.push(columns::join(
+            &attributes[index],
+            &args.buffer_encoding.decode(path_as_bytes(&name)),
+        ));
+        shown[index] = name;
+    }
+    let edited = args.editor.edit(&lines)?;
+    let edited = destination_files(edited, args.buffer_encoding, args.quiet)?;
+    if edited.len() != source_files.len() {
+        report_line_count(&names, &edited);
+        return Err(Error::LineCount(source_files.len(), edited.len()));
+    }
+    let mut destination_files = vec![PathBuf::new(); edited.len()];
+    let mut line_numbers = vec![0; edited.len()];
+    for (index, (line_number, line)) in order.into_iter().zip(edited) {
+        destination_files[index] = line;
+        line_numbers[index] = line_number;
+    }
+    let mut changes = vec![];
+    for ((destination, old_values), &line_number) in destination_files
+        .iter_mut()
+        .zip(attributes)
+        .zip(&line_numbers)
+    {
+        if args.columns.is_empty() {
+            changes.push(vec![]);
+            continue;
+        }
+        let line = path_as_bytes(destination).to_vec();
+        let (values, name) = columns::split(&line, args.columns.len())
+            .ok_or_else(|| Error::InvalidFileList.at_line(line_number))?;
+        let mut row = vec![];
+        for ((column, old), new) in args.columns.iter().zip(old_values).zip(values) {
+            if old == new {
+                continue;
+            }
+            if old == "-" || !column.is_valid(&new) {
+                return Err(Error::InvalidAttribute(column.name().into(), new).at_line(line_number));
+            }
+            row.push((column.clone(), new));
+        }
+        changes.push(row);
+        *destination = PathBuf::from(ffi::OsStr::from_bytes(name));
+    }
+    if !args.preserve_whitespace {
+        for ((destination, shown), &line_number) in
+            destination_files.iter_mut().zip(&shown).zip(&line_numbers)
+        {
+            let name = path_as_bytes(destination);
+            let trimmed = name.trim_ascii();
+            if destination == shown || trimmed.len() == name.len() {
+                continue;
+            }
+            eprintln!(
+                "bulkrename: {}removed whitespace around '{}', use --preserve-whitespace to keep it",
+                line_prefix(line_number),
+                ffi::OsStr::from_bytes(trimmed).quoted()
+            );
+            *destination = PathBuf::from(ffi::OsStr::from_bytes(trimmed));
+        }
+    }
+    if let Some(prefix) = prefix {
+        for destination in &mut destination_files {
+            *destination = prefix.join(&destination);
+        }
+    }
+    Ok((destination_files, changes, line_numbers))
+}
+
+/// Edits `source_files` in rounds of `chunk` files. Line numbers refer to the
+/// buffer of each round.
+fn edit_chunks<P>(source_files: &[P], chunk: usize, args: &Args) -> Result<Edited, Error>
+where
+    P: AsRef<Path>,
+{
+    let mut edited: Edited = (vec![], vec![], vec![]);
+    for (round, files) in source_files.chunks(chunk).enumerate() {
+        let start = round * chunk;
+        if !args.quiet {
+            println!(
+                "editing files {} to {} of {}",
+                start + 1,
+                start + files.len(),
+                source_files.len()
+            );
+        }
+        let (destinations, attributes, lines) = edit(files, args)?;
+        edited.0.extend(destinations);
+        edited.1.extend(attributes);
+        edited.2.extend(lines);
+    }
+    Ok(edited)
+}
+
+/// Writes the outcome of the renames to the file given with `--report`.
+fn write_report(entries: &[report::Entry], args: &Args) -> Result<(), Error> {
+    match &args.report {
+        Some(path) => report::write(path, entries).with_path(Operation::Write, path),
+        None => Ok(()),
+    }
+}
+
+/// Destinations, flags and line numbers of the rows of a plan.
+type Planned = (Vec<PathBuf>, Vec<csv::Flags>, Vec<usize>);
+
+/// Prints the renames of `source_files` to `destination_files` as a CSV plan
+/// or a unified diff.
+fn write_plan<P>(source_files: &[P], destination_files: &[PathBuf], args: &Args) -> io::Result<()>
+where
+    P: AsRef<Path>,
+{
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    if args.diff {
+        patch::write(&mut stdout, source_files, destination_files)
+    } else {
+        let rows: Vec<_> = source_files
+            .iter()
+            .map(AsRef::as_ref)
+            .zip(destination_files.iter().map(AsRef::as_ref))
+            .filter(|(source, destination)| source != destination)
+            .collect();
+        let strategies = parallel::map(&rows, |(source, destination)| {
+            filesystem::strategy(source, destination).map_or("", |strategy| strategy.name())
+        });
+        csv::write(
+            &mut stdout,
+            rows.iter()
+                .zip(strategies)
+                .map(|(&(source, destination), strategy)| (source, destination, strategy)),
+        )
+    }
+}
+
+/// Renames the files among `paths` that match one of `rules`, creating the
+/// directories they are moved to. Returns the new paths.
+fn organize(
+    paths: &[PathBuf],
+    config: &config::Config,
+    args: &Args,
+) -> Result<Vec<PathBuf>, Error> {
+    let mut sources = vec![];
+    let mut destinations = vec![];
+    for path in paths {
+        if is_dir(path) {
+            continue;
+        }
+        match rules::destination(&config.rules, path) {
+            Some(destination) if destination != *path => {
+                sources.push(path.clone());
+                destinations.push(destination);
+            }
+            _ => {}
+        }
+    }
+    let keep = unprotected(&sources, config, args)?;
+    let mut keep_destination = keep.iter();
+    destinations.retain(|_| *keep_destination.next().unwrap());
+    let mut keep_source = keep.iter();
+    sources.retain(|_| *keep_source.next().unwrap());
+    if sources.is_empty() {
+        return Ok(destinations);
+    }
+    if args.command == Command::Plan || args.diff {
+        write_plan(&sources, &destinations, args)?;
+        return Ok(destinations);
+    }
+    let count = sources.len();
+    let planned = (
+        destinations.clone(),
+        vec![csv::Flags::default(); count],
+        vec![0; count],
+    );
+    // Directories are only created once the renames have been checked.
+    let args = Args {
+        parents: true,
+        ..args.clone()
+    };
+    bulk_rename(&sources, Some(planned), &args)?;
+    Ok(destinations)
+}
+
+/// Renames files arriving in `dirs` by the rules in `config` until a signal
+/// is received.
+#[cfg(target_os = "linux")]
+fn watch(dirs: &[PathBuf], config: &config::Config, args: &Args) -> Result<(), Error> {
+    let mut watcher = watch::Watcher::new(dirs)?;
+    signals::install()?;
+    // Files are reported again under the new names they are given.
+    let mut renamed = HashSet::new();
+    while !signals::interrupted() {
+        let mut paths = watcher.wait(Duration::from_secs(1))?;
+        if paths.is_empty() {
+            continue;
+        }
+        // Files arriving together are renamed together.
+        loop {
+            let more = watcher.wait(Duration::from_millis(200))?;
+            if more.is_empty() {
+                break;
+            }
+            paths.extend(more);
+        }
+        paths.sort();
+        paths.dedup();
+        paths.retain(|path| !renamed.remove(path) && fs::symlink_metadata(path).is_ok());
+        match organize(&paths, config, args) {
+            Ok(destinations) => renamed.extend(destinations),
+            Err(err @ Error::Interrupted(..)) => return Err(err),
+            Err(err) => eprintln!("bulkrename: {}", err),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn watch(_: &[PathBuf], _: &config::Config, _: &Args) -> Result<(), Error> {
+    Err(io::Error::from(io::ErrorKind::Unsupported).into())
+}
+
+/// Renames `source_files` to the destinations and per-row flags in `planned`
+/// or, if no plan is given, to the names given in an editor.
+fn bulk_rename<P>(source_files: &[P], planned: Option<Planned>, args: &Args) -> Result<(), Error>
+where
+    P: AsRef<Path> + std::marker::Sync,
+{
+    for path in source_files {
+        check_path(path.as_ref(), args)?;
+    }
+    let mut snapshots = parallel::map(source_files, |path| Snapshot::of(path));
+    let mut identities: Vec<_> = snapshots
+        .iter()
+        .map(|snapshot| snapshot.as_ref().map(|snapshot| snapshot.identity))
+        .collect();
+    hardlinks::warn_shared(source_files, &identities);
+    let mut locks = lock::Locks::default();
+    lock_dirs(&mut locks, source_files.iter().map(AsRef::as_ref))?;
+    let (mut destination_files, mut flags, mut attributes, mut lines) = match planned {
+        Some((destination_files, flags, lines)) => {
+            let attributes = vec![vec![]; destination_files.len()];
+            (destination_files, flags, attributes, lines)
+        }
+        None => {
+            let (destination_files, attributes, lines) = match args.chunk {
+                Some(chunk) => edit_chunks(source_files, chunk, args)?,
+                None => edit(source_files, args)?,
+            };
+            let flags = vec![csv::Flags::default(); destination_files.len()];
+            (destination_files, flags, attributes, lines)
+        }
+    };
+    for (source, destination) in source_files.iter().zip(destination_files.iter_mut()) {
+        let source = source.as_ref();
+        if source == destination {
+            continue;
+        }
+        if args.expand {
+            *destination = expand(destination)?;
+        }
+        if args.relative_to_source && is_bare_name(destination) {
+            *destination = source.with_file_name(&destination);
+        }
+        if same_path(source, destination) {
+            *destination = source.to_owned();
+        }
+    }
+    let sidecars = if args.sidecars.is_empty() {
+        vec![]
+    } else {
+        let renames: Vec<_> = source_files
+            .iter()
+            .zip(destination_files.iter())
+            .enumerate()
+            .filter(|(_, (source, destination))| source.as_ref() != *destination)
+            .map(|(row, (source, destination))| (row, source.as_ref(), destination.as_path()))
+            .collect();
+        let listed: HashSet<_> = source_files.iter().map(AsRef::as_ref).collect();
+        sidecars::find(&renames, &listed, &args.sidecars)?
+    };
+    let listed = source_files.len();
+    let mut sources: Vec<&Path> = source_files.iter().map(AsRef::as_ref).collect();
+    for sidecar in &sidecars {
+        sources.push(&sidecar.source);
+        destination_files.push(sidecar.destination.clone());
+        flags.push(flags[sidecar.row]);
+        attributes.push(vec![]);
+        lines.push(lines[sidecar.row]);
+        let snapshot = Snapshot::of(&sidecar.source);
+        identities.push(snapshot.as_ref().map(|snapshot| snapshot.identity));
+        snapshots.push(snapshot);
+    }
+    let source_files = &sources[..];
+    if let Some(keep_extension) = args.keep_extension {
+        let mut changed = 0;
+        for ((source, destination), line) in source_files
+            .iter()
+            .zip(destination_files.iter_mut())
+            .zip(&lines)
+        {
+            let source = *source;
+            if source == destination || source.extension() == destination.extension() {
+                continue;
+            }
+            if keep_extension == KeepExtension::Restore {
+                *destination = with_extension_of(destination, source);
+                continue;
+            }
+            eprintln!(
+                "bulkrename: {}new name {} of {} changes the extension",
+                line_prefix(*line),
+                destination.quoted(),
+                source.quoted()
+            );
+            changed += 1;
+        }
+        if changed > 0 {
+            return Err(Error::ExtensionsChanged(changed));
+        }
+    }
+    let mut resolver = conflict::Resolver::new(args.answer);
+    let renamed: Vec<_> = source_files
+        .iter()
+        .zip(destination_files.iter())
+        .enumerate()
+        .filter(|(_, (source, destination))| *source != *destination)
+        .map(|(index, (source, _))| (index, *source))
+        .collect();
+    let mut currents: HashMap<_, _> = renamed
+        .iter()
+        .map(|(index, _)| *index)
+        .zip(parallel::map(&renamed, |(_, source)| Snapshot::of(source)))
+        .collect();
+    let mut stale = 0;
+    for (index, ((((source, destination), snapshot), identity), line)) in source_files
+        .iter()
+        .zip(destination_files.iter_mut())
+        .zip(snapshots)
+        .zip(identities.iter_mut())
+        .zip(&lines)
+        .enumerate()
+    {
+        let source = *source;
+        let current = match currents.remove(&index) {
+            Some(current) => current,
+            None => continue,
+        };
+        if current == snapshot {
+            continue;
+        }
+        eprintln!(
+            "bulkrename: {}{} was modified, replaced or removed while the file list was being edited",
+            line_prefix(*line),
+            source.quoted()
+        );
+        match (args.stale, current) {
+            (Stale::Abort, _) => stale += 1,
+            (Stale::Ask, Some(current))
+                if resolver.confirm(&format!("rename '{}' anyway?", source.quoted()))? =>
+            {
+                *identity = Some(current.identity);
+            }
+            _ => *destination = source.to_owned(),
+        }
+    }
+    if stale > 0 {
+        return Err(Error::Stale(stale));
+    }
+    let mut changes = 0;
+    let dirs: Vec<_> = source_files
+        .iter()
+        .zip(destination_files.iter())
+        .filter(|(source, destination)| *source != *destination)
+        .map(|(_, destination)| parent_dir(destination))
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    // Directories whose limits cannot be determined are reported below.
+    let mut limits: HashMap<_, _> = dirs
+        .iter()
+        .zip(parallel::map(&dirs, |dir| filesystem::name_limits(dir)))
+        .filter_map(|(dir, limits)| Some((dir.to_path_buf(), limits.ok()?)))
+        .collect();
+    for ((source, destination), &line) in source_files
+        .iter()
+        .zip(destination_files.iter())
+        .zip(&lines)
+    {
+        if *source != destination {
+            check_path(destination, args).map_err(|err| err.at_line(line))?;
+            if !args.allow_control_chars {
+                check_control_chars(source, destination).map_err(|err| err.at_line(line))?;
+            }
+            check_length(destination, &mut limits, args.parents)
+                .map_err(|err| err.at_line(line))?;
+            changes += 1;
+        }
+    }
+    match args.max {
+        Some(max) if changes > max => return Err(Error::TooManyChanges(changes, max)),
+        _ => {}
+    }
+    check_case_collisions(
+        source_files
+            .iter()
+            .zip(destination_files.iter())
+            .zip(lines.iter().copied())
+            .filter(|((source, destination), _)| *source != *destination)
+            .map(|((_, destination), line)| (line, destination.as_ref())),
+    )?;
+    if args.command == Command::Plan {
+        write_plan(source_files, &destination_files, args)?;
+        return match changes {
+            0 => Ok(()),
+            _ => Err(Error::Pending),
+        };
+    }
+    lock_dirs(&mut locks, destination_files.iter().map(AsRef::as_ref))?;
+    let unwritable = filesystem::check_writable(
+        source_files
+            .iter()
+            .map(AsRef::as_ref)
+            .zip(destination_files.iter().map(AsRef::as_ref))
+            .filter(|(source, destination)| source != destination),
+        args.parents,
+    );
+    if !unwritable.is_empty() {
+        for problem in unwritable {
+            eprintln!(
+                "bulkrename: cannot rename files in {}: {}",
+                problem.dir.quoted(),
+                problem.reason
+            );
+        }
+        return Err(Error::NotWritable);
+    }
+    let shortages = filesystem::check_space(
+        source_files
+            .iter()
+            .map(AsRef::as_ref)
+            .zip(destination_files.iter().map(AsRef::as_ref))
+            .filter(|(source, destination)| source != destination),
+    )?;
+    if !shortages.is_empty() {
+        for shortage in shortages {
+            eprintln!(
+                "bulkrename: {} needs {} but only {} are available",
+                shortage.mount_point.quoted(),
+                filesystem::format_size(shortage.required),
+                filesystem::format_size(shortage.available)
+            );
+        }
+        return Err(Error::InsufficientSpace);
+    }
+    if args.table {
+        let rows: Vec<_> = source_files
+            .iter()
+            .zip(destination_files.iter())
+            .zip(flags.iter())
+            .enumerate()
+            .filter(|(_, ((source, destination), _))| *source != *destination)
+            .map(|(index, ((source, destination), flags))| table::Row {
+                source,
+                destination,
+                replace: args.replace || flags.replace || flags.backup,
+                sidecar: index >= listed,
+            })
+            .collect();
+        if !rows.is_empty() {
+            print!("{}", table::render(&rows));
+            if !resolver.confirm(&format!("rename {} files?", rows.len()))? {
+                return Err(Error::Cancelled);
+            }
+        }
+    }
+    if args.parents {
+        for (source, destination) in source_files.iter().zip(destination_files.iter()) {
+            let dir = parent_dir(destination);
+            if *source == destination || fs::symlink_metadata(dir).is_ok() {
+                continue;
+            }
+            create_dirs(dir, args.dir_mode).with_path(Operation::CreateDir, dir)?;
+            if !args.quiet {
+                println!("creating directory {}", dir.quoted());
+            }
+        }
+    }
+    if args.sandbox {
+        let replacing = flags.iter().any(|flags| flags.replace || flags.backup);
+        let sandbox_args = args.with_flags(csv::Flags {
+            replace: replacing,
+            backup: false,
+        });
+        enter_sandbox(source_files, &destination_files, &sandbox_args)?;
+    }
+    let hardlinks = match &args.hardlink_root {
+        Some(root) => {
+            let wanted = source_files
+                .iter()
+                .zip(destination_files.iter())
+                .zip(identities.iter())
+                .filter(|((source, destination), _)| *source != *destination)
+                .filter_map(|(_, identity)| *identity)
+                .collect();
+            hardlinks::find(root, &wanted).with_path(Operation::List, root)?
+        }
+        None => HashMap::new(),
+    };
+    let sources = source_files
+        .iter()
+        .map(|path| symlinks::absolute_lexical(path))
+        .collect::<io::Result<HashSet<_>>>()
+        .with_path(Operation::Inspect, Path::new("."))?;
+    signals::install()?;
+    let mut renames = vec![];
+    let mut unchanged = vec![];
+    let mut failures = vec![];
+    let mut report = vec![];
+    let mut attribute_changes = 0;
+    for (((((source, destination), identity), flags), attributes), line) in source_files
+        .iter()
+        .zip(destination_files.iter())
+        .zip(identities)
+        .zip(flags)
+        .zip(attributes)
+        .zip(lines)
+    {
+        if signals::interrupted() {
+            break;
+        }
+        let source = *source;
+        if source == destination {
+            unchanged.push(source);
+            match change_attributes(source, &attributes, args) {
+                Ok(()) => attribute_changes += attributes.len(),
+                Err(err) if args.keep_going => failures.push((line, source, err)),
+                Err(err) => return Err(err.at_line(line)),
+            }
+            continue;
+        }
+        let row_args;
+        let args = if flags.replace || flags.backup {
+            row_args = args.with_flags(flags);
+            &row_args
+        } else {
+            args
+        };
+        let started = Instant::now();
+        let strategy = match args.report {
+            Some(_) => {
+                filesystem::strategy(source, destination).map_or("", |strategy| strategy.name())
+            }
+            None => "",
+        };
+        let result =
+            rename_entry(source, destination, identity, &mut resolver, args).and_then(|renamed| {
+                let path = renamed.as_deref().unwrap_or(source);
+                change_attributes(path, &attributes, args)?;
+                attribute_changes += attributes.len();
+                Ok(renamed)
+            });
+        if args.report.is_some() {
+            report.push(report::Entry {
+                source: source.to_owned(),
+                destination: destination.clone(),
+                status: match result {
+                    Ok(Some(_)) => "renamed",
+                    Ok(None) => "skipped",
+                    Err(_) => "failed",
+                },
+                strategy,
+                error: result.as_ref().err().map(ToString::to_string),
+                duration: started.elapsed(),
+            });
+        }
+        match result {
+            Ok(Some(destination)) => {
+                let siblings = identity
+                    .and_then(|identity| hardlinks.get(&identity))
+                    .map(Vec::as_slice)
+                    .unwrap_or_default();
+                let first = renames.len();
+                renames.extend(rename_hardlinks(
+                    source,
+                    &destination,
+                    siblings,
+                    &sources,
+                    args,
+                ));
+                renames.push((source.to_owned(), destination));
+                if args.sync == Some(Sync::Each) {
+                    sync_renames(&renames[first..])?;
+                }
+            }
+            Ok(None) => unchanged.push(source),
+            Err(err) if args.keep_going => failures.push((line, source, err)),
+            Err(err) => {
+                write_report(&report, args)?;
+                return Err(err.at_line(line));
+            }
+        }
+    }
+    if args.sync == Some(Sync::Batch) {
+        sync_renames(&renames)?;
+    }
+    write_report(&report, args)?;
+    if let Some(terminator) = args.print_renamed {
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        for (_, destination) in &renames {
+            stdout.write_all(path_as_bytes(destination))?;
+            stdout.write_all(&[terminator])?;
+        }
+        stdout.flush()?;
+    }
+    if !args.quiet {
+        println!("{} files renamed", renames.len());
+    }
+    if attribute_changes > 0 && !args.quiet {
+        println!("{} attributes changed", attribute_changes);
+    }
+    if signals::interrupted() {
+        return Err(Error::Interrupted(renames.len(), changes));
+    }
+    if args.retarget_links {
+        let mut count = 0;
+        for (source, destination) in &renames {
+            let retargeted = symlinks::retarget(source, destination)
+                .with_path(Operation::UpdateLink, destination)?;
+            if let Some(target) = retargeted {
+                if !args.quiet {
+                    println!(
+                        "updating link {} to point to {}",
+                        destination.quoted(),
+                        target.quoted()
+                    );
+                }
+                count += 1;
+            }
+        }
+        if count > 0 && !args.quiet {
+            println!("{} moved links updated", count);
+        }
+    }
+    if args.fix_symlinks && !renames.is_empty() {
+        let paths = renames
+            .iter()
+            .map(|(_, destination)| destination.as_ref())
+            .chain(unchanged);
+        let count = symlinks::fix(&renames, paths, &args.symlink_dirs, args.quiet)?;
+        if !args.quiet {
+            println!("{} links updated", count);
+        }
+    }
+    if !failures.is_empty() {
+        for (line, source, err) in &failures {
+            match err {
+                Error::Failed { .. } => eprintln!("bulkrename: {}{}", line_prefix(*line), err),
+                _ => eprintln!(
+                    "bulkrename: {}failed to rename {}: {}",
+                    line_prefix(*line),
+                    source.quoted(),
+                    err
+                ),
+            }
+        }
+        return Err(Error::Failures(failures.len()));
+    }
+    Ok(())
+}
+
+/// Appends the entries below the directory `dir` to `paths`, listing the
+/// contents of each directory before the directory itself so that they are
+/// renamed first. Directories already in `visited`, reached again through a
+/// bind mount, are reported and left out, and false is returned for them.
+fn walk_dir(
+    dir: &Path,
+    paths: &mut Vec<PathBuf>,
+    visited: &mut HashMap<Identity, PathBuf>,
+) -> Result<bool, Error> {
+    if let Some(identity) = Identity::of(dir) {
+        if let Some(first) = visited.get(&identity) {
+            eprintln!(
+                "bulkrename: {} is the directory {} again, not listing it twice",
+                dir.quoted(),
+                first.quoted()
+            );
+            return Ok(false);
+        }
+        visited.insert(identity, dir.to_owned());
+    }
+    let mut entries = fs::read_dir(dir)
+        .and_then(|entries| {
+            entries
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<io::Result<Vec<_>>>()
+        })
+        .with_path(Operation::List, dir)?;
+    entries.sort();
+    for entry in entries {
+        if is_dir(&entry) && !walk_dir(&entry, paths, visited)? {
+            continue;
+        }
+        paths.push(entry);
+    }
+    Ok(true)
+}
+
+/// Returns the file the symbolic link `link` points to, following chains of
+/// links, or `link` itself if it is not a link.
+fn dereference(link: &Path) -> Result<PathBuf, Error> {
+    /// The number of links Linux follows before giving up with `ELOOP`.
+    const MAX_LINKS: usize = 40;
+    let mut path = link.to_owned();
+    for _ in 0..MAX_LINKS {
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if !metadata.file_type().is_symlink() => return Ok(path),
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound && path != link => {
+                return Err(Error::DanglingLink(link.to_owned()))
+            }
+            Err(err) => return Err(err).with_path(Operation::Inspect, &path),
+        }
+        let target = fs::read_link(&path).with_path(Operation::Inspect, &path)?;
+        path = path.parent().unwrap_or_else(|| Path::new("")).join(target);
+    }
+    Err(Error::LinkLoop(link.to_owned()))
+}
+
+/// Returns which of `paths` match none of the protected patterns in `config`,
+/// warning about the others, or refuses if the configuration says so.
+fn unprotected<P: AsRef<Path>>(
+    paths: &[P],
+    config: &config::Config,
+    args: &Args,
+) -> Result<Vec<bool>, Error> {
+    if args.allow_system || config.protect.is_empty() {
+        return Ok(vec![true; paths.len()]);
+    }
+    let mut keep = vec![];
+    for path in paths {
+        let path = path.as_ref();
+        // Patterns with a slash match whole absolute paths.
+        let absolute = absolute(path).unwrap_or_else(|_| path.to_owned());
+        let protected = config
+            .protect
+            .iter()
+            .any(|pattern| pattern.matches(&absolute));
+        if protected && config.protect_action == config::ProtectAction::Refuse {
+            return Err(Error::DangerousPath(path.to_owned(), "protected pattern"));
+        }
+        if protected {
+            eprintln!("bulkrename: skipping protected file {}", path.quoted());
+        }
+        keep.push(!protected);
+    }
+    Ok(keep)
+}
+
+/// Keeps the input rows for which `keep` is true.
+fn retain_rows(source_files: &mut Vec<PathBuf>, planned: &mut Option<Planned>, keep: &[bool]) {
+    let mut keep_source = keep.iter();
+    source_files.retain(|_| *keep_source.next().unwrap());
+    if let Some((destinations, flags, lines)) = planned {
+        let mut keep_destination = keep.iter();
+        destinations.retain(|_| *keep_destination.next().unwrap());
+        let mut keep_flags = keep.iter();
+        flags.retain(|_| *keep_flags.next().unwrap());
+        let mut keep_lines = keep.iter();
+        lines.retain(|_| *keep_lines.next().unwrap());
+    }
+}
+
+/// Runs the command given by the arguments. Plans report errors separately
+/// from pending renames, as they are told apart by the exit status.
+fn run() -> Result<(), Error> {
+    let args = Args::parse()?;
+    match args.command {
+        Command::Plan => execute(args).map_err(|err| match err {
+            Error::Pending => Error::Pending,
+            err => Error::Planning(Box::new(err)),
+        }),
+        _ => execute(args),
+    }
+}
+
+fn execute(mut args: Args) -> Result<(), Error> {
+    if args.show_help {
+        print!(
+            "{}",
+            match args.command {
+                Command::Edit => USAGE,
+                Command::Plan => PLAN_USAGE,
+                Command::Apply => APPLY_USAGE,
+                Command::Mv => MV_USAGE,
+                Command::Organize => ORGANIZE_USAGE,
+            }
+        );
+        return Ok(());
+    }
+    if args.watch {
+        if args.files.is_empty() {
+            return Err(Error::InvalidArguments("--watch", "directories to watch"));
+        }
+        let config = config::load()?;
+        if config.rules.is_empty() {
+            return Err(Error::NoRules);
+        }
+        args.sandbox = false;
+        return watch(&args.files, &config, &args);
+    }
+    if args.swap {
+        let (a, b) = (&args.files[0], &args.files[1]);
+        for path in [a, b] {
+            check_path(path, &args)?;
+            fs::symlink_metadata(path).with_path(Operation::Inspect, path)?;
+        }
+        // A skipped file leaves nothing to swap it with.
+        if unprotected(&args.files, &config::load()?, &args)?.contains(&false) {
+            return Ok(());
+        }
+        let mut locks = lock::Locks::default();
+        lock_dirs(&mut locks, [a.as_ref(), b.as_ref()])?;
+        exchange(a, b).with_paths(Operation::Exchange, a, b)?;
+        if !args.quiet {
+            println!("swapping {} and {}", a.quoted(), b.quoted());
+        }
+        return Ok(());
+    }
+    if args.command == Command::Organize {
+        let config = config::load()?;
+        if config.rules.is_empty() {
+            return Err(Error::NoRules);
+        }
+        if args.files.is_empty() {
+            args.files.push(".".into());
+        }
+        let mut paths = vec![];
+        for dir in &args.files {
+            let mut entries = fs::read_dir(dir)
+                .with_path(Operation::List, dir)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<io::Result<Vec<_>>>()
+                .with_path(Operation::List, dir)?;
+            entries.sort();
+            paths.extend(entries);
+        }
+        organize(&paths, &config, &args)?;
+        return Ok(());
+    }
+    let mut planned = None;
+    let mut source_files = if args.command == Command::Mv {
+        let destination = args.files.pop().unwrap_or_default();
+        planned = Some((vec![destination], vec![csv::Flags::default()], vec![0]));
+        mem::take(&mut args.files)
+    } else if let Some(plan) = match (&args.from_csv, &args.from_diff) {
+        (Some(path), _) => Some(csv::read(path)?),
+        (None, Some(path)) => Some(patch::read(path)?),
+        (None, None) => None,
+    } {
+        planned = Some((plan.destinations, plan.flags, plan.lines));
+        plan.sources
+    } else if args.files.is_empty() {
+        source_files(args.quiet)?
+    } else if args.also_stdin || args.files.iter().any(|file| file == Path::new("-")) {
+        let mut files = vec![];
+        let mut read_stdin = false;
+        for file in mem::take(&mut args.files) {
+            if file == Path::new("-") {
+                if !read_stdin {
+                    files.extend(source_files(args.quiet)?);
+                    read_stdin = true;
+                }
+            } else {
+                files.push(file);
+            }
+        }
+        if !read_stdin {
+            files.extend(source_files(args.quiet)?);
+        }
+        files
+    } else {
+        mem::take(&mut args.files)
+    };
+    let mut missing = 0;
+    if args.ignore_missing {
+        let exists: Vec<_> = source_files
+            .iter()
+            .map(|path| {
+                let exists = fs::symlink_metadata(path).is_ok();
+                if !exists {
+                    eprintln!("bulkrename: skipping missing file {}", path.quoted());
+                    missing += 1;
+                }
+                exists
+            })
+            .collect();
+        retain_rows(&mut source_files, &mut planned, &exists);
+    }
+    if args.absolute {
+        source_files = source_files
+            .iter()
+            .map(|path| absolute(path).with_path(Operation::Inspect, path))
+            .collect::<Result<_, _>>()?;
+    }
+    if args.recursive && planned.is_none() {
+        let mut paths = vec![];
+        let mut visited = HashMap::new();
+        for path in source_files {
+            if !is_dir(&path) {
+                paths.push(path);
+                continue;
+            }
+            let mut entries = vec![];
+            walk_dir(&path, &mut entries, &mut visited)?;
+            for entry in entries {
+                if args.dangling != Dangling::Include && is_dangling(&entry) {
+                    if args.dangling == Dangling::Error {
+                        return Err(Error::DanglingEntry(entry));
+                    }
+                    eprintln!("bulkrename: skipping dangling link {}", entry.quoted());
+                    continue;
+                }
+                paths.push(entry);
+            }
+        }
+        source_files = paths;
+    }
+    if args.dereference {
+        let mut targets = HashSet::new();
+        let mut keep = vec![];
+        for path in &mut source_files {
+            let target = dereference(path)?;
+            let first = targets.insert(Identity::of(&target));
+            if first && target != *path && !args.quiet {
+                eprintln!(
+                    "bulkrename: renaming {}, which {} points to",
+                    target.quoted(),
+                    path.quoted()
+                );
+            }
+            keep.push(first);
+            *path = target;
+        }
+        retain_rows(&mut source_files, &mut planned, &keep);
+    }
+    if !args.only.is_empty() || !args.exclude.is_empty() {
+        let keep: Vec<_> = source_files
+            .iter()
+            .map(|path| {
+                (args.only.is_empty() || args.only.iter().any(|pattern| pattern.matches(path)))
+                    && !args.exclude.iter().any(|pattern| pattern.matches(path))
+            })
+            .collect();
+        retain_rows(&mut source_files, &mut planned, &keep);
+    }
+    let config = config::load()?;
+    if let Some(name) = &args.preset {
+        let template = config.presets.get(name);
+        args.template = Some(
+            template
+                .ok_or_else(|| Error::UnknownPreset(name.clone()))?
+                .clone(),
+        );
+    }
+    if let Some(name) = &args.plugin {
+        args.plugin = Some(plugin::find(name)?);
+    }
+    let keep = unprotected(&source_files, &config, &args)?;
+    retain_rows(&mut source_files, &mut planned, &keep);
+    if args.pick && !source_files.is_empty() {
+        let picked = pick::pick(&source_files)?.ok_or(Error::Cancelled)?;
+        retain_rows(&mut source_files, &mut planned, &picked);
+    }
+    if args.select && !source_files.is_empty() {
+        let selected = select::select(&source_files)?.ok_or(Error::Cancelled)?;
+        retain_rows(&mut source_files, &mut planned, &selected);
+    }
+    if !source_files.is_empty() {
+        bulk_rename(source_files.as_ref(), planned, &args)?;
+    }
+    if missing > 0 && !args.quiet {
+        println!("{} missing files skipped", missing);
+    }
+    Ok(())
+}
+
+fn main() {
+    match run() {
+        Ok(()) => {}
+        Err(Error::Pending) => process::exit(1),
+        Err(err) => {
+            eprintln!("bulkrename: {}", err);
+            process::exit(match err {
+                Error::Interrupted(..) => 130,
+                Error::Planning(_) => 2,
+                _ => 1,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn common_prefix_of_parents() {
+        assert_eq!(
+            common_prefix(&["a/b/c.txt", "a/b/d/e.txt", "a/b/f.txt"]),
+            Some(PathBuf::from("a/b"))
+        );
+        assert_eq!(common_prefix(&["a/b.txt", "c/d.txt"]), None);
+        assert_eq!(common_prefix(&["a/b.txt", "c.txt"]), None);
+        assert_eq!(common_prefix::<&str>(&[]), None);
+    }
+
+    #[test]
+    fn normalize_lexically() {
+        assert_eq!(normalize(Path::new("./a/./b/../c")), Path::new("a/c"));
+        assert_eq!(normalize(Path::new("a/../../b")), Path::new("../b"));
+        assert_eq!(normalize(Path::new("/../a")), Path::new("/a"));
+    }
+
+    #[test]
+    fn same_path_ignores_current_dir_components() {
+        assert!(same_path(Path::new("./photo.jpg"), Path::new("photo.jpg")));
+        assert!(same_path(Path::new("a//b/"), Path::new("a/./b")));
+        assert!(!same_path(Path::new("a/../b"), Path::new("b")));
+        assert!(!same_path(Path::new("a"), Path::new("b")));
+    }
+
+    /// An editor that changes the buffer with a function instead of asking.
+    struct Fake(fn(&[Vec<u8>]) -> Vec<Vec<u8>>);
+
+    impl editor::Editor for Fake {
+        fn edit(&self, lines: &[Vec<u8>]) -> Result<Vec<Vec<u8>>, Error> {
+            Ok((self.0)(lines))
+        }
+    }
+
+    fn args(options: &[&str]) -> Args {
+        Args::parse_from(options.iter().map(Into::into)).unwrap()
+    }
+
+    #[test]
+    fn edit_maps_lines_back_to_files() {
+        static EDITOR: Fake = Fake(|lines| {
+            assert_eq!(lines, [b"a.txt".to_vec(), b"b.txt".to_vec()]);
+            vec![b"\xef\xbb\xbfz.txt\r".to_vec(), vec![], b" y.txt ".to_vec()]
+        });
+        let mut args = args(&["--sort=bytes"]);
+        args.editor = &EDITOR;
+        let (destinations, _, lines) = edit(&["b.txt", "a.txt"], &args).unwrap();
+        assert_eq!(
+            destinations,
+            [PathBuf::from("y.txt"), PathBuf::from("z.txt")]
+        );
+        assert_eq!(lines, [3, 1]);
+    }
+
+    #[test]
+    fn edit_refuses_removed_lines() {
+        static EDITOR: Fake = Fake(|lines| lines[1..].to_vec());
+        let mut args = args(&[]);
+        args.editor = &EDITOR;
+        assert!(matches!(
+            edit(&["a.txt", "b.txt"], &args),
+            Err(Error::LineCount(2, 1))
+        ));
     }
 }
@@ -0,0 +1,40 @@
+//! Running independent file system queries in parallel, which speeds up
+//! checking large numbers of files on network file systems.
+
+use std::thread;
+
+/// Lists shorter than this are processed on the current thread.
+const MIN_PARALLEL: usize = 256;
+
+/// Most threads used, since the work is waiting on the file system rather
+/// than on the processor.
+const MAX_THREADS: usize = 16;
+
+/// Applies `f` to each item of `items` using several threads and returns the
+/// results in the same order.
+pub fn map<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let threads = thread::available_parallelism()
+        .map(|threads| threads.get() * 2)
+        .unwrap_or(1)
+        .min(MAX_THREADS);
+    if items.len() < MIN_PARALLEL || threads <= 1 {
+        return items.iter().map(f).collect();
+    }
+    let chunk = items.len().div_ceil(threads);
+    let f = &f;
+    thread::scope(|scope| {
+        let handles: Vec<_> = items
+            .chunks(chunk)
+            .map(|items| scope.spawn(move || items.iter().map(f).collect::<Vec<_>>()))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
@@ -0,0 +1,229 @@
+//! Plans as unified diffs of the list of names, for reviewing them with
+//! tools that understand diffs.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use crate::csv::{Flags, Plan};
+use crate::quote::Quote;
+use crate::{parent_dir, Context, Error, Operation};
+
+fn write_line<W>(writer: &mut W, prefix: u8, path: &Path) -> io::Result<()>
+where
+    W: Write,
+{
+    let bytes = path.as_os_str().as_bytes();
+    if bytes.contains(&b'\n') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "cannot show {} in a diff because it contains a line break",
+                path.quoted()
+            ),
+        ));
+    }
+    writer.write_all(&[prefix])?;
+    writer.write_all(bytes)?;
+    writer.write_all(b"\n")
+}
+
+/// Writes the renames of `sources` to `destinations` as a unified diff
+/// without context lines, with a hunk for each run of renamed files in the
+/// same directory. Lines are numbered as if each list had a line per file.
+pub fn write<W, S, D>(writer: &mut W, sources: &[S], destinations: &[D]) -> io::Result<()>
+where
+    W: Write,
+    S: AsRef<Path>,
+    D: AsRef<Path>,
+{
+    writer.write_all(b"--- a/names\n+++ b/names\n")?;
+    let mut index = 0;
+    while index < sources.len() {
+        if sources[index].as_ref() == destinations[index].as_ref() {
+            index += 1;
+            continue;
+        }
+        let dir = parent_dir(sources[index].as_ref());
+        let start = index;
+        while index < sources.len()
+            && sources[index].as_ref() != destinations[index].as_ref()
+            && parent_dir(sources[index].as_ref()) == dir
+        {
+            index += 1;
+        }
+        let count = index - start;
+        writeln!(
+            writer,
+            "@@ -{},{} +{},{} @@ {}",
+            start + 1,
+            count,
+            start + 1,
+            count,
+            dir.quoted()
+        )?;
+        for path in &sources[start..index] {
+            write_line(writer, b'-', path.as_ref())?;
+        }
+        for path in &destinations[start..index] {
+            write_line(writer, b'+', path.as_ref())?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the numbers of old and new lines in a hunk from its `@@` header.
+fn hunk_lengths(header: &[u8]) -> Option<(usize, usize)> {
+    // The text after the second `@@` may be in any encoding.
+    let header = String::from_utf8_lossy(header);
+    let mut ranges = header.strip_prefix("@@ ")?.split(' ');
+    let length = |range: &str, sign: char| -> Option<usize> {
+        let range = range.strip_prefix(sign)?;
+        match range.split_once(',') {
+            Some((start, length)) => {
+                start.parse::<usize>().ok()?;
+                length.parse().ok()
+            }
+            None => range.parse::<usize>().ok().map(|_| 1),
+        }
+    };
+    let old = length(ranges.next()?, '-')?;
+    let new = length(ranges.next()?, '+')?;
+    (ranges.next()? == "@@").then_some((old, new))
+}
+
+/// Reads a plan from a unified diff of a list of names, such as one written
+/// by `write` or by `diff -u` of two listings. The names removed and added
+/// in each group of changed lines are paired up in order, so each group has
+/// to remove as many names as it adds.
+pub fn read(path: &Path) -> Result<Plan, Error> {
+    let content = fs::read(path).with_path(Operation::Read, path)?;
+    parse(&content)
+}
+
+fn parse(content: &[u8]) -> Result<Plan, Error> {
+    let mut plan = Plan {
+        sources: vec![],
+        destinations: vec![],
+        flags: vec![],
+        lines: vec![],
+    };
+    let mut removed = vec![];
+    let mut added = vec![];
+    // The old and new lines left in the current hunk, counted so that names
+    // starting with `-- ` or `++ ` are not mistaken for file headers.
+    let mut remaining: (usize, usize) = (0, 0);
+    let lines = content
+        .split(|&byte| byte == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .enumerate()
+        .map(|(index, line)| (index + 1, line));
+    // A final empty line ends the last group.
+    for (number, line) in lines.chain(Some((0, &b""[..]))) {
+        let in_hunk = remaining != (0, 0);
+        let change = match line.first() {
+            Some(b'-') if in_hunk && remaining.0 > 0 => {
+                remaining.0 -= 1;
+                removed.push(&line[1..]);
+                true
+            }
+            Some(b'+') if in_hunk && remaining.1 > 0 => {
+                remaining.1 -= 1;
+                added.push((number, &line[1..]));
+                true
+            }
+            Some(b'\\') => true,
+            _ => false,
+        };
+        if change {
+            continue;
+        }
+        if removed.len() != added.len() {
+            let start = added.first().map_or(number, |(number, _)| *number);
+            return Err(Error::InvalidPlan(
+                start,
+                format!("{} names removed but {} added", removed.len(), added.len()),
+            ));
+        }
+        for (source, (number, destination)) in removed.drain(..).zip(added.drain(..)) {
+            plan.sources.push(OsStr::from_bytes(source).into());
+            plan.destinations
+                .push(OsStr::from_bytes(destination).into());
+            plan.flags.push(Flags::default());
+            plan.lines.push(number);
+        }
+        if in_hunk {
+            // A context line.
+            remaining = (remaining.0.saturating_sub(1), remaining.1.saturating_sub(1));
+        } else if line.starts_with(b"@@ ") {
+            remaining = hunk_lengths(line)
+                .ok_or_else(|| Error::InvalidPlan(number, "invalid hunk header".into()))?;
+        }
+    }
+    Ok(plan)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn renames(diff: &str) -> Vec<(PathBuf, PathBuf, usize)> {
+        let plan = parse(diff.as_bytes()).unwrap();
+        plan.sources
+            .into_iter()
+            .zip(plan.destinations)
+            .zip(plan.lines)
+            .map(|((source, destination), line)| (source, destination, line))
+            .collect()
+    }
+
+    fn rename(source: &str, destination: &str, line: usize) -> (PathBuf, PathBuf, usize) {
+        (source.into(), destination.into(), line)
+    }
+
+    #[test]
+    fn written_plans_read_back() {
+        let sources = ["a", "b", "c", "dir/d", "dir/e"];
+        let destinations = ["A", "b", "C", "dir/D", "dir/e"];
+        let mut diff = vec![];
+        write(&mut diff, &sources, &destinations).unwrap();
+        assert_eq!(
+            renames(std::str::from_utf8(&diff).unwrap()),
+            [
+                rename("a", "A", 5),
+                rename("c", "C", 8),
+                rename("dir/d", "dir/D", 11)
+            ]
+        );
+    }
+
+    #[test]
+    fn context_lines() {
+        let diff = "--- old\n+++ new\n@@ -1,4 +1,4 @@\n a\n-b\n-c\n+B\n+C\n d\n";
+        assert_eq!(renames(diff), [rename("b", "B", 7), rename("c", "C", 8)]);
+    }
+
+    #[test]
+    fn names_that_look_like_headers() {
+        let diff = "--- a/names\n+++ b/names\n@@ -1,2 +1,2 @@\n--- x\n-b\n++++ y\n+B\n";
+        assert_eq!(
+            renames(diff),
+            [rename("-- x", "+++ y", 6), rename("b", "B", 7)]
+        );
+    }
+
+    #[test]
+    fn several_files() {
+        let diff = "--- a\n+++ b\n@@ -1 +1 @@\n-x\n+y\n--- c\n+++ d\n@@ -3 +3 @@\n-z\n+w\n";
+        assert_eq!(renames(diff), [rename("x", "y", 5), rename("z", "w", 10)]);
+    }
+
+    #[test]
+    fn unbalanced_groups() {
+        assert!(parse(b"@@ -1,2 +1,1 @@\n-a\n-b\n+c\n").is_err());
+        assert!(parse(b"@@ -1,x +1,1 @@\n-a\n+b\n").is_err());
+    }
+}
@@ -0,0 +1,180 @@
+//! Narrowing the input list down with fuzzy matching before editing.
+
+use std::io;
+use std::path::Path;
+
+use crate::quote::Quote;
+use crate::terminal::{truncate, Key, Terminal};
+
+const HELP: &str = "tab: mark  enter: accept  esc: quit";
+
+/// Returns a score for how well `query` matches `text` or `None` if the
+/// characters of `query` do not appear in `text` in order. Matching is
+/// case-insensitive unless `query` contains uppercase characters. Matches at
+/// the start of words and consecutive matches score higher.
+pub fn score(query: &str, text: &str) -> Option<i64> {
+    let case_sensitive = query.chars().any(char::is_uppercase);
+    let fold = |c: char| {
+        if case_sensitive {
+            c
+        } else {
+            c.to_lowercase().next().unwrap_or(c)
+        }
+    };
+    let mut query = query.chars().map(fold).peekable();
+    let mut score = 0;
+    let mut previous: Option<char> = None;
+    let mut last_match: Option<usize> = None;
+    for (index, c) in text.chars().enumerate() {
+        let wanted = match query.peek() {
+            Some(&wanted) => wanted,
+            None => break,
+        };
+        if fold(c) == wanted {
+            query.next();
+            score += 1;
+            match previous {
+                None | Some('/') | Some('-') | Some('_') | Some('.') | Some(' ') => score += 8,
+                _ => {}
+            }
+            match last_match {
+                Some(last) if last + 1 == index => score += 6,
+                Some(last) => score -= ((index - last - 1) as i64).min(3),
+                None => {}
+            }
+            last_match = Some(index);
+        }
+        previous = Some(c);
+    }
+    if query.peek().is_some() {
+        None
+    } else {
+        Some(score)
+    }
+}
+
+struct Picker<'a> {
+    names: Vec<String>,
+    query: String,
+    marked: Vec<bool>,
+    /// Indices of the names matching the query, best first.
+    matches: Vec<usize>,
+    cursor: usize,
+    top: usize,
+    terminal: &'a mut Terminal,
+}
+
+impl Picker<'_> {
+    fn update_matches(&mut self) {
+        let mut scored: Vec<_> = self
+            .names
+            .iter()
+            .enumerate()
+            .filter_map(|(index, name)| score(&self.query, name).map(|score| (score, index)))
+            .collect();
+        scored.sort_by(|(a_score, a_index), (b_score, b_index)| {
+            b_score.cmp(a_score).then(a_index.cmp(b_index))
+        });
+        self.matches = scored.into_iter().map(|(_, index)| index).collect();
+        self.cursor = 0;
+        self.top = 0;
+    }
+
+    fn draw(&mut self) -> io::Result<()> {
+        let (rows, cols) = self.terminal.size();
+        let page = rows.saturating_sub(1).max(1);
+        if self.cursor < self.top {
+            self.top = self.cursor;
+        } else if self.cursor >= self.top + page {
+            self.top = self.cursor + 1 - page;
+        }
+        let marked = self.marked.iter().filter(|&&marked| marked).count();
+        let mut screen = String::from("\x1b[H\x1b[2J");
+        let header = format!(
+            "> {}  ({}/{}, {} marked)  {}",
+            self.query,
+            self.matches.len(),
+            self.names.len(),
+            marked,
+            HELP
+        );
+        screen.push_str(truncate(&header, cols));
+        for (position, &index) in self.matches.iter().enumerate().skip(self.top).take(page) {
+            let mark = if self.marked[index] { '*' } else { ' ' };
+            let line = format!("{} {}", mark, self.names[index]);
+            screen.push_str("\r\n");
+            if position == self.cursor {
+                screen.push_str("\x1b[7m");
+            }
+            screen.push_str(truncate(&line, cols));
+            if position == self.cursor {
+                screen.push_str("\x1b[0m");
+            }
+        }
+        self.terminal.write(&screen)
+    }
+
+    /// Returns which names were picked: the marked ones or, if none are
+    /// marked, all that match the query.
+    fn picked(&self) -> Vec<bool> {
+        if self.marked.contains(&true) {
+            return self.marked.clone();
+        }
+        let mut picked = vec![false; self.names.len()];
+        for &index in &self.matches {
+            picked[index] = true;
+        }
+        picked
+    }
+}
+
+/// Lets the user narrow `paths` down by typing a fuzzy query. Returns which
+/// paths were picked or `None` if the user quit.
+pub fn pick<P>(paths: &[P]) -> io::Result<Option<Vec<bool>>>
+where
+    P: AsRef<Path>,
+{
+    let mut terminal = Terminal::open()?;
+    let mut picker = Picker {
+        names: paths
+            .iter()
+            .map(|path| path.as_ref().quoted().into_owned())
+            .collect(),
+        query: String::new(),
+        marked: vec![false; paths.len()],
+        matches: vec![],
+        cursor: 0,
+        top: 0,
+        terminal: &mut terminal,
+    };
+    picker.update_matches();
+    loop {
+        picker.draw()?;
+        let last = picker.matches.len().saturating_sub(1);
+        match picker.terminal.read_key()? {
+            Key::Up => picker.cursor = picker.cursor.saturating_sub(1),
+            Key::Down => picker.cursor = (picker.cursor + 1).min(last),
+            Key::PageUp => picker.cursor = picker.cursor.saturating_sub(10),
+            Key::PageDown => picker.cursor = (picker.cursor + 10).min(last),
+            Key::Home => picker.cursor = 0,
+            Key::End => picker.cursor = last,
+            Key::Tab => {
+                if let Some(&index) = picker.matches.get(picker.cursor) {
+                    picker.marked[index] = !picker.marked[index];
+                    picker.cursor = (picker.cursor + 1).min(last);
+                }
+            }
+            Key::Backspace => {
+                picker.query.pop();
+                picker.update_matches();
+            }
+            Key::Char(c) if !c.is_control() => {
+                picker.query.push(c);
+                picker.update_matches();
+            }
+            Key::Enter => return Ok(Some(picker.picked())),
+            Key::Escape | Key::Interrupt => return Ok(None),
+            _ => {}
+        }
+    }
+}
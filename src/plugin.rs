@@ -0,0 +1,86 @@
+//! External programs that propose new names, kept in the `plugins`
+//! directory next to the configuration file.
+//!
+//! A plugin is run once for each editing session. It reads the paths of the
+//! files, one per line, on its standard input and prints a new path for each
+//! of them, in the same order, on its standard output.
+
+use std::ffi::OsString;
+use std::io::{Read, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use crate::config;
+use crate::Error;
+
+/// Returns the program of the plugin `name`, which is taken as a path if it
+/// contains a slash.
+pub fn find(name: &Path) -> Result<PathBuf, Error> {
+    if name.as_os_str().as_bytes().contains(&b'/') {
+        return Ok(name.to_owned());
+    }
+    match config::plugins_dir() {
+        Some(dir) if dir.join(name).is_file() => Ok(dir.join(name)),
+        _ => Err(Error::Plugin(name.to_owned(), "not found".into())),
+    }
+}
+
+/// Runs `program` on `paths` and returns the new paths it prints.
+pub fn run(program: &Path, paths: &[&Path]) -> Result<Vec<PathBuf>, Error> {
+    let failed = |message: String| Error::Plugin(program.to_owned(), message);
+    let mut input = vec![];
+    for path in paths {
+        let path = path.as_os_str().as_bytes();
+        if path.contains(&b'\n') {
+            return Err(failed(
+                "names with line breaks cannot be passed to plugins".into(),
+            ));
+        }
+        input.extend_from_slice(path);
+        input.push(b'\n');
+    }
+    let mut child = Command::new(program)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|err| failed(err.to_string()))?;
+    let mut stdin = child.stdin.take().unwrap();
+    // Written from another thread so that a plugin printing names before it
+    // has read all of them cannot block.
+    let writer = thread::spawn(move || stdin.write_all(&input));
+    let mut output = vec![];
+    child
+        .stdout
+        .take()
+        .unwrap()
+        .read_to_end(&mut output)
+        .map_err(|err| failed(err.to_string()))?;
+    let status = child.wait().map_err(|err| failed(err.to_string()))?;
+    if !status.success() {
+        return Err(failed(format!("exited with {}", status)));
+    }
+    // A plugin that does not read all names breaks the pipe, which is only
+    // an error if it also printed too few names.
+    let _ = writer.join();
+    if output.last() == Some(&b'\n') {
+        output.pop();
+    }
+    let names: Vec<PathBuf> = if output.is_empty() {
+        vec![]
+    } else {
+        output
+            .split(|&byte| byte == b'\n')
+            .map(|name| PathBuf::from(OsString::from_vec(name.to_vec())))
+            .collect()
+    };
+    if names.len() != paths.len() {
+        return Err(failed(format!(
+            "printed {} names instead of {}",
+            names.len(),
+            paths.len()
+        )));
+    }
+    Ok(names)
+}
@@ -0,0 +1,112 @@
+//! Showing names safely on a terminal, with control characters, invalid
+//! UTF-8 and characters that reorder text escaped like `ls -b` does.
+
+use std::borrow::Cow;
+use std::ffi::OsStr;
+use std::fmt::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+pub trait Quote {
+    /// Returns the name with backslash escapes in place of anything that
+    /// could change the terminal or how the name looks.
+    fn quoted(&self) -> Cow<'_, str>;
+}
+
+impl Quote for OsStr {
+    fn quoted(&self) -> Cow<'_, str> {
+        quote(self.as_bytes())
+    }
+}
+
+impl Quote for str {
+    fn quoted(&self) -> Cow<'_, str> {
+        quote(self.as_bytes())
+    }
+}
+
+impl Quote for Path {
+    fn quoted(&self) -> Cow<'_, str> {
+        self.as_os_str().quoted()
+    }
+}
+
+/// Whether `c` has to be escaped: control characters, and the formatting
+/// characters that change the direction of the text after them.
+fn is_unsafe(c: char) -> bool {
+    c.is_control()
+        || matches!(c, '\u{200e}' | '\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}')
+}
+
+fn push_escaped(quoted: &mut String, c: char) {
+    match c {
+        '\\' => quoted.push_str("\\\\"),
+        '\x07' => quoted.push_str("\\a"),
+        '\x08' => quoted.push_str("\\b"),
+        '\t' => quoted.push_str("\\t"),
+        '\n' => quoted.push_str("\\n"),
+        '\x0b' => quoted.push_str("\\v"),
+        '\x0c' => quoted.push_str("\\f"),
+        '\r' => quoted.push_str("\\r"),
+        c if c.is_ascii() => {
+            let _ = write!(quoted, "\\{:03o}", c as u32);
+        }
+        c if is_unsafe(c) => {
+            let _ = write!(quoted, "\\u{{{:x}}}", c as u32);
+        }
+        c => quoted.push(c),
+    }
+}
+
+fn quote(bytes: &[u8]) -> Cow<'_, str> {
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        if !text.chars().any(|c| c == '\\' || is_unsafe(c)) {
+            return Cow::Borrowed(text);
+        }
+    }
+    let mut quoted = String::new();
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            if c == '\\' || is_unsafe(c) {
+                push_escaped(&mut quoted, c);
+            } else {
+                quoted.push(c);
+            }
+        }
+        for byte in chunk.invalid() {
+            let _ = write!(quoted, "\\{:03o}", byte);
+        }
+    }
+    Cow::Owned(quoted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quoted(bytes: &[u8]) -> String {
+        OsStr::from_bytes(bytes).quoted().into_owned()
+    }
+
+    #[test]
+    fn plain_names_are_borrowed() {
+        assert!(matches!(OsStr::new("café.txt").quoted(), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn control_characters() {
+        assert_eq!(quoted(b"a\nb\tc"), "a\\nb\\tc");
+        assert_eq!(quoted(b"\x1b[31m"), "\\033[31m");
+        assert_eq!(quoted(b"a\\b"), "a\\\\b");
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        assert_eq!(quoted(b"caf\xe9.txt"), "caf\\351.txt");
+    }
+
+    #[test]
+    fn direction_overrides() {
+        assert_eq!(quoted("a\u{202e}txt.exe".as_bytes()), "a\\u{202e}txt.exe");
+    }
+}
@@ -0,0 +1,118 @@
+//! Reports of the outcome of each rename, for auditing large migrations.
+
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::csv;
+
+/// The outcome of a rename.
+pub struct Entry {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    /// `renamed`, `skipped` or `failed`.
+    pub status: &'static str,
+    pub strategy: &'static str,
+    pub error: Option<String>,
+    pub duration: Duration,
+}
+
+fn write_csv<W>(writer: &mut W, entries: &[Entry]) -> io::Result<()>
+where
+    W: Write,
+{
+    writer.write_all(b"source,destination,status,strategy,error,duration\n")?;
+    for entry in entries {
+        csv::write_field(writer, entry.source.as_os_str().as_bytes())?;
+        writer.write_all(b",")?;
+        csv::write_field(writer, entry.destination.as_os_str().as_bytes())?;
+        write!(writer, ",{},{},", entry.status, entry.strategy)?;
+        csv::write_field(writer, entry.error.as_deref().unwrap_or("").as_bytes())?;
+        writeln!(writer, ",{:.6}", entry.duration.as_secs_f64())?;
+    }
+    Ok(())
+}
+
+/// Formats `bytes` as a JSON string. Bytes that are not valid UTF-8 are
+/// written as the lone surrogates `\udc80` to `\udcff`, as Python's
+/// `surrogateescape` error handler does, so the name can be recovered.
+fn json_string(bytes: &[u8]) -> String {
+    let mut json = String::from("\"");
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '"' => json.push_str("\\\""),
+                '\\' => json.push_str("\\\\"),
+                '\n' => json.push_str("\\n"),
+                '\r' => json.push_str("\\r"),
+                '\t' => json.push_str("\\t"),
+                c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+                c => json.push(c),
+            }
+        }
+        for byte in chunk.invalid() {
+            json.push_str(&format!("\\udc{:02x}", byte));
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// Writes `entries` as a JSON array of objects.
+fn write_json<W>(writer: &mut W, entries: &[Entry]) -> io::Result<()>
+where
+    W: Write,
+{
+    writer.write_all(b"[")?;
+    for (index, entry) in entries.iter().enumerate() {
+        if index > 0 {
+            writer.write_all(b",")?;
+        }
+        write!(
+            writer,
+            "\n  {{\"source\": {}, \"destination\": {}, \"status\": \"{}\", \"strategy\": \"{}\", \"error\": {}, \"duration\": {:.6}}}",
+            json_string(entry.source.as_os_str().as_bytes()),
+            json_string(entry.destination.as_os_str().as_bytes()),
+            entry.status,
+            entry.strategy,
+            entry
+                .error
+                .as_deref()
+                .map_or_else(|| "null".into(), |error| json_string(error.as_bytes())),
+            entry.duration.as_secs_f64()
+        )?;
+    }
+    writer.write_all(b"\n]\n")
+}
+
+/// Writes `entries` to `path`, as JSON if its name ends with `.json` and as
+/// CSV otherwise.
+pub fn write(path: &Path, entries: &[Entry]) -> io::Result<()> {
+    let mut writer = io::BufWriter::new(fs::File::create(path)?);
+    match path.extension() {
+        Some(extension) if extension.eq_ignore_ascii_case("json") => {
+            write_json(&mut writer, entries)?
+        }
+        _ => write_csv(&mut writer, entries)?,
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_escapes() {
+        assert_eq!(json_string(b"a\"b\\c\n"), r#""a\"b\\c\n""#);
+        assert_eq!(json_string(b"\x1b"), r#""\u001b""#);
+        assert_eq!(json_string("café".as_bytes()), "\"café\"");
+    }
+
+    #[test]
+    fn json_invalid_utf8() {
+        assert_eq!(json_string(b"caf\xe9.txt"), r#""caf\udce9.txt""#);
+    }
+}
@@ -0,0 +1,72 @@
+//! Rules from the configuration file that give new names to files matching
+//! a pattern.
+
+use std::fs;
+use std::mem::MaybeUninit;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use crate::ascii;
+use crate::glob::Pattern;
+
+/// A rule giving files matching `pattern` the name `template`.
+#[derive(Clone)]
+pub struct Rule {
+    pub pattern: Pattern,
+    /// The new path relative to the directory of the file, in which `{name}`,
+    /// `{stem}`, `{ext}`, `{year}`, `{month}` and `{day}` are replaced by the
+    /// file name, the name without and the extension, and the date of the
+    /// last modification. `{name|ascii}`, `{stem|ascii}` and `{ext|ascii}`
+    /// are transliterated to ASCII.
+    pub template: String,
+}
+
+/// Returns the year, month and day of `time` in local time.
+fn local_date(time: i64) -> (i32, i32, i32) {
+    let time = time as libc::time_t;
+    let mut tm = MaybeUninit::<libc::tm>::zeroed();
+    let tm = unsafe {
+        libc::localtime_r(&time, tm.as_mut_ptr());
+        tm.assume_init()
+    };
+    (tm.tm_year + 1900, tm.tm_mon + 1, tm.tm_mday)
+}
+
+/// Returns the path given to `path` by `template`, or `None` if the name is
+/// not valid UTF-8.
+pub fn expand(template: &str, path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or(name);
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("");
+    let mut result = template.to_owned();
+    for (field, value) in [("name", name), ("stem", stem), ("ext", extension)] {
+        result = result.replace(&format!("{{{}}}", field), value).replace(
+            &format!("{{{}|ascii}}", field),
+            &ascii::transliterate(value),
+        );
+    }
+    if ["{year}", "{month}", "{day}"]
+        .iter()
+        .any(|field| result.contains(field))
+    {
+        let (year, month, day) = local_date(fs::symlink_metadata(path).ok()?.mtime());
+        result = result
+            .replace("{year}", &format!("{:04}", year))
+            .replace("{month}", &format!("{:02}", month))
+            .replace("{day}", &format!("{:02}", day));
+    }
+    Some(path.with_file_name("").join(result))
+}
+
+/// Returns the new path given to `path` by the first matching rule, or
+/// `None` if no rule matches or the name is not valid UTF-8.
+pub fn destination(rules: &[Rule], path: &Path) -> Option<PathBuf> {
+    let rule = rules.iter().find(|rule| rule.pattern.matches(path))?;
+    expand(&rule.template, path)
+}
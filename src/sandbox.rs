@@ -0,0 +1,139 @@
+//! Restricting file system modifications with Landlock.
+//!
+//! Once the plan is known, only the directories it touches need to be
+//! writable. The sandbox is applied on a best-effort basis: kernels without
+//! Landlock support leave the process unrestricted.
+
+use std::fs;
+use std::io;
+use std::os::raw;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+const CREATE_RULESET_VERSION: raw::c_uint = 1;
+const RULE_PATH_BENEATH: raw::c_int = 1;
+
+const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+const ACCESS_FS_REMOVE_DIR: u64 = 1 << 4;
+const ACCESS_FS_REMOVE_FILE: u64 = 1 << 5;
+const ACCESS_FS_MAKE_CHAR: u64 = 1 << 6;
+const ACCESS_FS_MAKE_DIR: u64 = 1 << 7;
+const ACCESS_FS_MAKE_REG: u64 = 1 << 8;
+const ACCESS_FS_MAKE_SOCK: u64 = 1 << 9;
+const ACCESS_FS_MAKE_FIFO: u64 = 1 << 10;
+const ACCESS_FS_MAKE_BLOCK: u64 = 1 << 11;
+const ACCESS_FS_MAKE_SYM: u64 = 1 << 12;
+const ACCESS_FS_REFER: u64 = 1 << 13;
+
+const ACCESS_FS_MODIFY: u64 = ACCESS_FS_WRITE_FILE
+    | ACCESS_FS_REMOVE_DIR
+    | ACCESS_FS_REMOVE_FILE
+    | ACCESS_FS_MAKE_CHAR
+    | ACCESS_FS_MAKE_DIR
+    | ACCESS_FS_MAKE_REG
+    | ACCESS_FS_MAKE_SOCK
+    | ACCESS_FS_MAKE_FIFO
+    | ACCESS_FS_MAKE_BLOCK
+    | ACCESS_FS_MAKE_SYM
+    | ACCESS_FS_REFER;
+
+// Landlock system calls share the same numbers on all architectures.
+const SYS_LANDLOCK_CREATE_RULESET: raw::c_long = 444;
+const SYS_LANDLOCK_ADD_RULE: raw::c_long = 445;
+const SYS_LANDLOCK_RESTRICT_SELF: raw::c_long = 446;
+
+#[repr(C)]
+struct RulesetAttr {
+    handled_access_fs: u64,
+}
+
+#[repr(C, packed)]
+struct PathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: i32,
+}
+
+fn abi_version() -> raw::c_long {
+    unsafe {
+        libc::syscall(
+            SYS_LANDLOCK_CREATE_RULESET,
+            std::ptr::null::<RulesetAttr>(),
+            0,
+            CREATE_RULESET_VERSION,
+        )
+    }
+}
+
+fn check(result: raw::c_long) -> io::Result<raw::c_long> {
+    if result == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(result)
+    }
+}
+
+fn add_rule(ruleset: raw::c_int, path: &Path, allowed_access: u64) -> io::Result<()> {
+    let file = fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_PATH | libc::O_CLOEXEC)
+        .open(path)?;
+    let attr = PathBeneathAttr {
+        allowed_access,
+        parent_fd: file.as_raw_fd(),
+    };
+    check(unsafe {
+        libc::syscall(
+            SYS_LANDLOCK_ADD_RULE,
+            ruleset,
+            RULE_PATH_BENEATH,
+            &attr as *const PathBeneathAttr,
+            0,
+        )
+    })?;
+    Ok(())
+}
+
+/// Restricts file system modifications to `directories` and whatever lies
+/// beneath them. Returns `false` if the kernel does not support a
+/// sufficiently recent version of Landlock.
+pub fn restrict<'a, I>(directories: I) -> io::Result<bool>
+where
+    I: IntoIterator<Item = &'a Path>,
+{
+    // Version 2 is the first one that allows files to be moved between
+    // directories.
+    if abi_version() < 2 {
+        return Ok(false);
+    }
+    let attr = RulesetAttr {
+        handled_access_fs: ACCESS_FS_MODIFY,
+    };
+    let ruleset = check(unsafe {
+        libc::syscall(
+            SYS_LANDLOCK_CREATE_RULESET,
+            &attr as *const RulesetAttr,
+            std::mem::size_of::<RulesetAttr>(),
+            0,
+        )
+    })? as raw::c_int;
+    let result = (|| {
+        for directory in directories {
+            // Directories that do not exist cannot be modified either.
+            if let Err(err) = add_rule(ruleset, directory, ACCESS_FS_MODIFY) {
+                if err.kind() != io::ErrorKind::NotFound {
+                    return Err(err);
+                }
+            }
+        }
+        // Prompts are written to the terminal and child processes may have
+        // their output discarded.
+        for device in &["/dev/tty", "/dev/null"] {
+            let _ = add_rule(ruleset, Path::new(device), ACCESS_FS_WRITE_FILE);
+        }
+        check(unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } as raw::c_long)?;
+        check(unsafe { libc::syscall(SYS_LANDLOCK_RESTRICT_SELF, ruleset, 0) })
+    })();
+    unsafe { libc::close(ruleset) };
+    result.map(|_| true)
+}
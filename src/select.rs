@@ -0,0 +1,82 @@
+//! Choosing the files to rename from the input list before editing.
+
+use std::io;
+use std::path::Path;
+
+use crate::quote::Quote;
+use crate::terminal::{truncate, Key, Terminal};
+
+const HELP: &str = "space: toggle  a: toggle all  enter: continue  q: quit";
+
+fn draw<P>(
+    terminal: &mut Terminal,
+    paths: &[P],
+    selected: &[bool],
+    cursor: usize,
+    top: usize,
+) -> io::Result<()>
+where
+    P: AsRef<Path>,
+{
+    let (rows, cols) = terminal.size();
+    let count = selected.iter().filter(|&&selected| selected).count();
+    let mut screen = String::from("\x1b[H\x1b[2J");
+    let header = format!("{} of {} selected  {}", count, paths.len(), HELP);
+    screen.push_str(truncate(&header, cols));
+    for (index, path) in paths.iter().enumerate().skip(top).take(rows - 1) {
+        let mark = if selected[index] { 'x' } else { ' ' };
+        let line = format!("[{}] {}", mark, path.as_ref().quoted());
+        screen.push_str("\r\n");
+        if index == cursor {
+            screen.push_str("\x1b[7m");
+        }
+        screen.push_str(truncate(&line, cols));
+        if index == cursor {
+            screen.push_str("\x1b[0m");
+        }
+    }
+    terminal.write(&screen)
+}
+
+/// Lets the user select which of `paths` to keep. All paths are selected
+/// initially. Returns `None` if the user quit.
+pub fn select<P>(paths: &[P]) -> io::Result<Option<Vec<bool>>>
+where
+    P: AsRef<Path>,
+{
+    let mut terminal = Terminal::open()?;
+    let mut selected = vec![true; paths.len()];
+    let mut cursor = 0;
+    let mut top = 0;
+    loop {
+        let page = terminal.size().0.saturating_sub(1).max(1);
+        if cursor < top {
+            top = cursor;
+        } else if cursor >= top + page {
+            top = cursor + 1 - page;
+        }
+        draw(&mut terminal, paths, &selected, cursor, top)?;
+        let last = paths.len().saturating_sub(1);
+        match terminal.read_key()? {
+            Key::Up | Key::Char('k') => cursor = cursor.saturating_sub(1),
+            Key::Down | Key::Char('j') => cursor = (cursor + 1).min(last),
+            Key::PageUp => cursor = cursor.saturating_sub(page),
+            Key::PageDown => cursor = (cursor + page).min(last),
+            Key::Home | Key::Char('g') => cursor = 0,
+            Key::End | Key::Char('G') => cursor = last,
+            Key::Char(' ') => {
+                if let Some(selected) = selected.get_mut(cursor) {
+                    *selected = !*selected;
+                }
+                cursor = (cursor + 1).min(last);
+            }
+            Key::Char('a') => {
+                let all = selected.iter().all(|&selected| selected);
+                selected.iter_mut().for_each(|selected| *selected = !all);
+            }
+            Key::Enter => return Ok(Some(selected)),
+            Key::Char('q') | Key::Escape | Key::Interrupt => return Ok(None),
+            _ => {}
+        }
+    }
+}
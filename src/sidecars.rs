@@ -0,0 +1,94 @@
+//! Finding sidecar files, such as `.xmp` metadata or `.srt` subtitles, that
+//! follow the files they belong to when those are renamed.
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use crate::{parent_dir, Context, Error, Operation};
+
+/// A sidecar to rename along with the file in row `row`.
+pub struct Sidecar {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    pub row: usize,
+}
+
+/// Returns the extension of the sidecar `name` of a file whose name or stem
+/// is `base`, if it is one of `extensions`.
+fn sidecar_extension<'a>(name: &'a [u8], base: &[u8], extensions: &[String]) -> Option<&'a [u8]> {
+    if name.len() <= base.len() + 1 || !name.starts_with(base) || name[base.len()] != b'.' {
+        return None;
+    }
+    let extension = &name[base.len() + 1..];
+    extensions
+        .iter()
+        .any(|wanted| wanted.as_bytes().eq_ignore_ascii_case(extension))
+        .then_some(extension)
+}
+
+fn with_extension(base: &OsStr, extension: &[u8]) -> OsString {
+    let mut name = base.to_owned();
+    name.push(".");
+    name.push(OsStr::from_bytes(extension));
+    name
+}
+
+/// Returns the files next to the renamed files in `renames` that have their
+/// stem, or their whole name, followed by one of `extensions`, compared
+/// case-insensitively, with new names that make the same change. Files in
+/// `listed` are renamed on their own and left out.
+pub fn find(
+    renames: &[(usize, &Path, &Path)],
+    listed: &HashSet<&Path>,
+    extensions: &[String],
+) -> Result<Vec<Sidecar>, Error> {
+    let mut entries: HashMap<&Path, Vec<OsString>> = HashMap::new();
+    let mut found: HashSet<PathBuf> = HashSet::new();
+    let mut sidecars = vec![];
+    for &(row, source, destination) in renames {
+        let (name, new_name) = match (source.file_name(), destination.file_name()) {
+            (Some(name), Some(new_name)) => (name, new_name),
+            _ => continue,
+        };
+        let dir = parent_dir(source);
+        if !entries.contains_key(dir) {
+            let mut names = fs::read_dir(dir)
+                .and_then(|entries| {
+                    entries
+                        .map(|entry| entry.map(|entry| entry.file_name()))
+                        .collect::<io::Result<Vec<_>>>()
+                })
+                .with_path(Operation::List, dir)?;
+            names.sort();
+            entries.insert(dir, names);
+        }
+        let stem = source.file_stem().unwrap_or(name);
+        let new_stem = destination.file_stem().unwrap_or(new_name);
+        for entry_name in &entries[dir] {
+            let path = source.with_file_name(entry_name);
+            if listed.contains(path.as_path()) || found.contains(&path) {
+                continue;
+            }
+            let entry_name = entry_name.as_bytes();
+            let new_entry_name = match (
+                sidecar_extension(entry_name, name.as_bytes(), extensions),
+                sidecar_extension(entry_name, stem.as_bytes(), extensions),
+            ) {
+                (Some(extension), _) => with_extension(new_name, extension),
+                (None, Some(extension)) => with_extension(new_stem, extension),
+                (None, None) => continue,
+            };
+            found.insert(path.clone());
+            sidecars.push(Sidecar {
+                source: path,
+                destination: destination.with_file_name(new_entry_name),
+                row,
+            });
+        }
+    }
+    Ok(sidecars)
+}
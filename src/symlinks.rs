@@ -0,0 +1,159 @@
+//! Updating symbolic links whose targets have been renamed.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::os::unix::fs::symlink;
+use std::path::{Component, Path, PathBuf};
+
+use crate::quote::Quote;
+use crate::{normalize, parent_dir, rename, Context, Error, Operation};
+
+/// Returns the lexically normalized absolute form of `path`.
+pub fn absolute_lexical(path: &Path) -> io::Result<PathBuf> {
+    Ok(normalize(&env::current_dir()?.join(path)))
+}
+
+/// Returns a relative path leading from the directory `base` to `path`. Both
+/// paths must be absolute and normalized.
+pub fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    let mut path_components = path.components().peekable();
+    let mut base_components = base.components().peekable();
+    while let (Some(a), Some(b)) = (path_components.peek(), base_components.peek()) {
+        if a != b {
+            break;
+        }
+        path_components.next();
+        base_components.next();
+    }
+    let mut result: PathBuf = base_components.map(|_| Component::ParentDir).collect();
+    result.extend(path_components);
+    if result.as_os_str().is_empty() {
+        result.push(".");
+    }
+    result
+}
+
+/// Returns where the file at `target` ended up after `renames`, if it or one
+/// of its ancestors was renamed.
+fn renamed_target(target: &Path, renames: &[(PathBuf, PathBuf)]) -> Option<PathBuf> {
+    renames.iter().find_map(|(old, new)| {
+        target.strip_prefix(old).ok().map(|rest| {
+            if rest.as_os_str().is_empty() {
+                new.clone()
+            } else {
+                new.join(rest)
+            }
+        })
+    })
+}
+
+/// Atomically replaces the symbolic link at `link` with one pointing to
+/// `target`.
+pub fn replace_link(link: &Path, target: &Path) -> io::Result<()> {
+    let mut temp_name = link.file_name().unwrap_or_default().to_owned();
+    temp_name.push(".bulkrename-link");
+    let temp = link.with_file_name(temp_name);
+    symlink(target, &temp)?;
+    rename(&temp, link, true).inspect_err(|_| {
+        let _ = fs::remove_file(&temp);
+    })
+}
+
+/// Changes the relative target of the symbolic link moved from `old` to `new`
+/// so that it points to the same file from its new directory. Returns the
+/// new target if the link was changed.
+pub fn retarget(old: &Path, new: &Path) -> io::Result<Option<PathBuf>> {
+    if !fs::symlink_metadata(new)?.file_type().is_symlink() {
+        return Ok(None);
+    }
+    let target = fs::read_link(new)?;
+    let old_dir = absolute_lexical(parent_dir(old))?;
+    let new_dir = absolute_lexical(parent_dir(new))?;
+    if target.is_absolute() || old_dir == new_dir {
+        return Ok(None);
+    }
+    let new_target = relative_path(&normalize(&old_dir.join(&target)), &new_dir);
+    replace_link(new, &new_target)?;
+    Ok(Some(new_target))
+}
+
+/// Points the symbolic link `link` to the new location of its target if the
+/// target was renamed. Returns the new target if the link was changed.
+fn fix_link(link: &Path, renames: &[(PathBuf, PathBuf)]) -> io::Result<Option<PathBuf>> {
+    let target = fs::read_link(link)?;
+    let link_dir = absolute_lexical(parent_dir(link))?;
+    let resolved = normalize(&link_dir.join(&target));
+    let new = match renamed_target(&resolved, renames) {
+        Some(new) => new,
+        None => return Ok(None),
+    };
+    let new_target = if target.is_absolute() {
+        new
+    } else {
+        relative_path(&new, &link_dir)
+    };
+    replace_link(link, &new_target)?;
+    Ok(Some(new_target))
+}
+
+fn walk(dir: &Path, links: &mut Vec<PathBuf>) -> Result<(), Error> {
+    for entry in fs::read_dir(dir).with_path(Operation::List, dir)? {
+        let entry = entry.with_path(Operation::List, dir)?;
+        let file_type = entry
+            .file_type()
+            .with_path(Operation::Inspect, &entry.path())?;
+        if file_type.is_symlink() {
+            links.push(entry.path());
+        } else if file_type.is_dir() {
+            walk(&entry.path(), links)?;
+        }
+    }
+    Ok(())
+}
+
+/// Updates symbolic links among `paths` and inside `dirs` whose targets were
+/// renamed according to `renames`. Returns the number of updated links.
+pub fn fix<'a, I>(
+    renames: &[(PathBuf, PathBuf)],
+    paths: I,
+    dirs: &[PathBuf],
+    quiet: bool,
+) -> Result<usize, Error>
+where
+    I: IntoIterator<Item = &'a Path>,
+{
+    let renames = renames
+        .iter()
+        .map(|(old, new)| Ok((absolute_lexical(old)?, absolute_lexical(new)?)))
+        .collect::<io::Result<Vec<_>>>()
+        .with_path(Operation::Inspect, Path::new("."))?;
+    let mut links: Vec<PathBuf> = paths
+        .into_iter()
+        .filter(|path| {
+            fs::symlink_metadata(path)
+                .map(|metadata| metadata.file_type().is_symlink())
+                .unwrap_or(false)
+        })
+        .map(From::from)
+        .collect();
+    for dir in dirs {
+        walk(dir, &mut links)?;
+    }
+    links.sort();
+    links.dedup();
+    let mut count = 0;
+    for link in links {
+        if let Some(target) = fix_link(&link, &renames).with_path(Operation::UpdateLink, &link)? {
+            if !quiet {
+                println!(
+                    "updating link {} to point to {}",
+                    link.quoted(),
+                    target.quoted()
+                );
+            }
+            count += 1;
+        }
+    }
+    Ok(count)
+}
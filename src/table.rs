@@ -0,0 +1,161 @@
+//! Previewing planned renames as an aligned table.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::filesystem::{self, Strategy};
+use crate::parallel;
+use crate::quote::Quote;
+
+/// A planned rename shown in the table.
+pub struct Row<'a> {
+    pub source: &'a Path,
+    pub destination: &'a Path,
+    /// Whether an existing destination may be replaced.
+    pub replace: bool,
+    /// Whether the file is renamed because of the file it belongs to.
+    pub sidecar: bool,
+}
+
+/// The mount points of the file systems of an old and a new name.
+type Mounts = (PathBuf, PathBuf);
+
+/// Where a row moves a file: the mount points of the file systems of the
+/// old and the new name, and whether it has to be copied.
+struct Placement {
+    mounts: Option<Mounts>,
+    strategy: Option<Strategy>,
+    size: u64,
+}
+
+impl Placement {
+    fn is_copy(&self) -> bool {
+        matches!(self.strategy, Some(Strategy::Clone) | Some(Strategy::Copy))
+    }
+}
+
+fn placement(row: &Row) -> Placement {
+    let strategy = filesystem::strategy(row.source, row.destination).ok();
+    let mounts = filesystem::mount_of(row.source)
+        .and_then(|source| Ok((source, filesystem::mount_of(row.destination)?)))
+        .ok();
+    let size = match strategy {
+        Some(Strategy::Rename) | None => 0,
+        Some(_) => filesystem::tree_size(row.source).unwrap_or(0),
+    };
+    Placement {
+        mounts,
+        strategy,
+        size,
+    }
+}
+
+/// Returns the heading of a group of rows moving files from the file system
+/// mounted at `source` to the one at `destination`.
+fn heading(source: &Path, destination: &Path, copies: usize, size: u64) -> String {
+    if copies == 0 {
+        return format!("on {}\n", source.quoted());
+    }
+    format!(
+        "from {} to {}, copying {} files of {}\n",
+        source.quoted(),
+        destination.quoted(),
+        copies,
+        filesystem::format_size(size)
+    )
+}
+
+/// Formats `rows` as a table with a marker, the old and the new name and
+/// flags for sidecars, for conflicts, for symbolic links, which are renamed themselves
+/// rather than the files they point to, and for files that are copied to
+/// another file system. The marker is `R` for renames within a directory and
+/// `M` for moves to another directory. Rows involving more than one file
+/// system are grouped under a line naming their mount points.
+pub fn render(rows: &[Row]) -> String {
+    let mut destinations = HashMap::new();
+    for row in rows {
+        *destinations.entry(row.destination).or_insert(0) += 1;
+    }
+    let sources: Vec<_> = rows.iter().map(|row| row.source).collect();
+    let names: Vec<_> = rows.iter().map(|row| row.source.quoted()).collect();
+    let placements = parallel::map(rows, placement);
+    let width = names
+        .iter()
+        .map(|name| name.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(3);
+    let mut lines = vec![];
+    for ((row, name), placement) in rows.iter().zip(&names).zip(&placements) {
+        let marker = if row.source.parent() == row.destination.parent() {
+            'R'
+        } else {
+            'M'
+        };
+        let mut flags = vec![];
+        if row.sidecar {
+            flags.push("sidecar");
+        }
+        if row
+            .source
+            .symlink_metadata()
+            .is_ok_and(|metadata| metadata.file_type().is_symlink())
+        {
+            flags.push("link");
+        }
+        if destinations[row.destination] > 1 {
+            flags.push("duplicate");
+        }
+        let exists = row.destination.symlink_metadata().is_ok();
+        if exists && !sources.contains(&row.destination) {
+            flags.push(if row.replace { "replace" } else { "exists" });
+        }
+        match placement.strategy {
+            Some(Strategy::Rename) | None => {}
+            Some(strategy) => flags.push(strategy.name()),
+        }
+        let padding = width - name.chars().count();
+        let mut line = format!(
+            "{} {}{:padding$}  {}",
+            marker,
+            name,
+            "",
+            row.destination.quoted(),
+            padding = padding
+        );
+        if !flags.is_empty() {
+            line.push_str(&format!("  ! {}", flags.join(", ")));
+        }
+        line.push('\n');
+        lines.push(line);
+    }
+    let mut groups: Vec<(Option<&Mounts>, Vec<usize>)> = vec![];
+    for (index, placement) in placements.iter().enumerate() {
+        let mounts = placement.mounts.as_ref();
+        match groups.iter_mut().find(|(group, _)| *group == mounts) {
+            Some((_, members)) => members.push(index),
+            None => groups.push((mounts, vec![index])),
+        }
+    }
+    let headings = groups.len() > 1 || placements.iter().any(Placement::is_copy);
+    let mut table = format!("  {:width$}  new\n", "old", width = width);
+    for (mounts, members) in groups {
+        if let (true, Some((source, destination))) = (headings, mounts) {
+            let copies: Vec<_> = members
+                .iter()
+                .map(|&index| &placements[index])
+                .filter(|placement| placement.is_copy())
+                .collect();
+            table.push_str(&heading(
+                source,
+                destination,
+                copies.len(),
+                copies.iter().map(|placement| placement.size).sum(),
+            ));
+        }
+        for index in members {
+            table.push_str(&lines[index]);
+        }
+    }
+    table
+}
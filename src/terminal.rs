@@ -0,0 +1,169 @@
+//! A minimal full screen terminal interface on the controlling terminal, used
+//! for interactively choosing the files to rename.
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::mem::MaybeUninit;
+use std::os::unix::io::AsRawFd;
+
+pub enum Key {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Enter,
+    Tab,
+    Backspace,
+    Escape,
+    /// Control-C, which does not raise a signal in raw mode.
+    Interrupt,
+    Char(char),
+}
+
+/// The controlling terminal in raw mode. The previous mode is restored when
+/// dropped.
+pub struct Terminal {
+    tty: fs::File,
+    saved: libc::termios,
+}
+
+impl Terminal {
+    pub fn open() -> io::Result<Self> {
+        let tty = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/tty")?;
+        let mut saved = MaybeUninit::<libc::termios>::zeroed();
+        if unsafe { libc::tcgetattr(tty.as_raw_fd(), saved.as_mut_ptr()) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let saved = unsafe { saved.assume_init() };
+        let mut raw = saved;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+        raw.c_iflag &= !(libc::IXON | libc::ICRNL);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(tty.as_raw_fd(), libc::TCSAFLUSH, &raw) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut terminal = Terminal { tty, saved };
+        // Switch to the alternate screen and hide the cursor.
+        terminal.write("\x1b[?1049h\x1b[?25l")?;
+        Ok(terminal)
+    }
+
+    /// Returns the number of rows and columns of the terminal.
+    pub fn size(&self) -> (usize, usize) {
+        let mut size = MaybeUninit::<libc::winsize>::zeroed();
+        if unsafe { libc::ioctl(self.tty.as_raw_fd(), libc::TIOCGWINSZ, size.as_mut_ptr()) } == -1 {
+            return (24, 80);
+        }
+        let size = unsafe { size.assume_init() };
+        match (size.ws_row, size.ws_col) {
+            (0, _) | (_, 0) => (24, 80),
+            (rows, cols) => (rows as usize, cols as usize),
+        }
+    }
+
+    pub fn write(&mut self, text: &str) -> io::Result<()> {
+        self.tty.write_all(text.as_bytes())?;
+        self.tty.flush()
+    }
+
+    fn read_byte(&mut self) -> io::Result<u8> {
+        let mut byte = [0];
+        loop {
+            match self.tty.read(&mut byte) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(_) => return Ok(byte[0]),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Returns true if more input arrives within a short time, which tells
+    /// escape sequences apart from the escape key.
+    fn input_pending(&self) -> bool {
+        let mut poll = libc::pollfd {
+            fd: self.tty.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        unsafe { libc::poll(&mut poll, 1, 50) > 0 }
+    }
+
+    pub fn read_key(&mut self) -> io::Result<Key> {
+        let byte = self.read_byte()?;
+        let key = match byte {
+            b'\r' | b'\n' => Key::Enter,
+            b'\t' => Key::Tab,
+            0x7f | 0x08 => Key::Backspace,
+            0x03 => Key::Interrupt,
+            0x1b if !self.input_pending() => Key::Escape,
+            0x1b => {
+                let kind = self.read_byte()?;
+                let code = self.read_byte()?;
+                match (kind, code) {
+                    (b'[', b'A') | (b'O', b'A') => Key::Up,
+                    (b'[', b'B') | (b'O', b'B') => Key::Down,
+                    (b'[', b'H') | (b'O', b'H') => Key::Home,
+                    (b'[', b'F') | (b'O', b'F') => Key::End,
+                    (b'[', digit) if digit.is_ascii_digit() => {
+                        // Consume the rest of the sequence up to '~'.
+                        let mut last = code;
+                        while last != b'~' && last.is_ascii_digit() {
+                            last = self.read_byte()?;
+                        }
+                        match digit {
+                            b'5' => Key::PageUp,
+                            b'6' => Key::PageDown,
+                            b'1' | b'7' => Key::Home,
+                            b'4' | b'8' => Key::End,
+                            _ => Key::Escape,
+                        }
+                    }
+                    _ => Key::Escape,
+                }
+            }
+            _ if byte < 0x80 => Key::Char(byte as char),
+            _ => {
+                // Collect the continuation bytes of a UTF-8 sequence.
+                let len = match byte {
+                    0xc0..=0xdf => 2,
+                    0xe0..=0xef => 3,
+                    _ => 4,
+                };
+                let mut bytes = vec![byte];
+                for _ in 1..len {
+                    bytes.push(self.read_byte()?);
+                }
+                match std::str::from_utf8(&bytes)
+                    .ok()
+                    .and_then(|s| s.chars().next())
+                {
+                    Some(c) => Key::Char(c),
+                    None => Key::Escape,
+                }
+            }
+        };
+        Ok(key)
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = self.write("\x1b[?25h\x1b[?1049l");
+        unsafe { libc::tcsetattr(self.tty.as_raw_fd(), libc::TCSAFLUSH, &self.saved) };
+    }
+}
+
+/// Returns `text` cut to at most `width` characters.
+pub fn truncate(text: &str, width: usize) -> &str {
+    match text.char_indices().nth(width) {
+        Some((index, _)) => &text[..index],
+        None => text,
+    }
+}
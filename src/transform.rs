@@ -0,0 +1,64 @@
+//! Transforms applied to file names before they are shown in the editor.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use crate::ascii;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Transform {
+    Lowercase,
+    Uppercase,
+    /// Transliteration to ASCII.
+    Ascii,
+}
+
+impl Transform {
+    fn apply(self, text: &str) -> String {
+        match self {
+            Transform::Lowercase => text.to_lowercase(),
+            Transform::Uppercase => text.to_uppercase(),
+            Transform::Ascii => ascii::transliterate(text),
+        }
+    }
+}
+
+/// The part of a file name transforms apply to.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Scope {
+    Name,
+    /// The name without the extension.
+    Stem,
+    /// The extension, without the dot.
+    Extension,
+}
+
+/// Applies `transforms` to the part of the file name of `path` selected by
+/// `scope`. Names that are not valid UTF-8 are left as they are.
+pub fn apply(transforms: &[Transform], scope: Scope, path: &Path) -> PathBuf {
+    if transforms.is_empty() {
+        return path.to_owned();
+    }
+    let name = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name,
+        None => return path.to_owned(),
+    };
+    let transform = |text: &str| {
+        transforms
+            .iter()
+            .fold(text.to_owned(), |text, transform| transform.apply(&text))
+    };
+    let stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or(name);
+    let extension = path.extension().and_then(|extension| extension.to_str());
+    let new_name = match (scope, extension) {
+        (Scope::Name, _) => transform(name),
+        (Scope::Stem, Some(extension)) => format!("{}.{}", transform(stem), extension),
+        (Scope::Stem, None) => transform(name),
+        (Scope::Extension, Some(extension)) => format!("{}.{}", stem, transform(extension)),
+        (Scope::Extension, None) => name.to_owned(),
+    };
+    path.with_file_name(OsString::from(new_name))
+}
@@ -0,0 +1,179 @@
+//! Moving files to the trash as described by the freedesktop.org Trash
+//! specification.
+
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::{self, Write};
+use std::mem::MaybeUninit;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use crate::{absolute, filesystem, parent_dir, rename};
+
+struct Trash {
+    /// Directory holding `files` and `info`.
+    root: PathBuf,
+    /// Directory that `Path` entries in trash info files are relative to.
+    base: Option<PathBuf>,
+}
+
+fn home_trash() -> io::Result<PathBuf> {
+    let data_home = match env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => env::var_os("HOME")
+            .map(|home| Path::new(&home).join(".local/share"))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "HOME is not set"))?,
+    };
+    Ok(data_home.join("Trash"))
+}
+
+fn top_directory_trash(top: &Path) -> io::Result<PathBuf> {
+    let uid = unsafe { libc::getuid() };
+    let shared = top.join(".Trash");
+    if let Ok(metadata) = fs::symlink_metadata(&shared) {
+        let sticky = metadata.permissions().mode() & libc::S_ISVTX != 0;
+        if metadata.is_dir() && sticky {
+            let trash = shared.join(uid.to_string());
+            if fs::create_dir_all(&trash).is_ok() {
+                return Ok(trash);
+            }
+        }
+    }
+    let trash = top.join(format!(".Trash-{}", uid));
+    fs::DirBuilder::new()
+        .mode(0o700)
+        .recursive(true)
+        .create(&trash)?;
+    Ok(trash)
+}
+
+fn find_trash(path: &Path) -> io::Result<Trash> {
+    let home = home_trash()?;
+    fs::DirBuilder::new()
+        .mode(0o700)
+        .recursive(true)
+        .create(&home)?;
+    let dev = fs::symlink_metadata(path)?.dev();
+    if fs::metadata(&home)?.dev() == dev {
+        return Ok(Trash {
+            root: home,
+            base: None,
+        });
+    }
+    let top = filesystem::mount_point(path.parent().unwrap_or(path), dev);
+    Ok(Trash {
+        root: top_directory_trash(&top)?,
+        base: Some(top),
+    })
+}
+
+fn percent_encode(path: &Path) -> String {
+    let mut encoded = String::new();
+    for &byte in path.as_os_str().as_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn deletion_date() -> String {
+    let now = unsafe { libc::time(std::ptr::null_mut()) };
+    let mut tm = MaybeUninit::<libc::tm>::zeroed();
+    let tm = unsafe {
+        libc::localtime_r(&now, tm.as_mut_ptr());
+        tm.assume_init()
+    };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        tm.tm_year + 1900,
+        tm.tm_mon + 1,
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec
+    )
+}
+
+/// Reserves a unique name in the trash by exclusively creating its info file.
+fn reserve(trash: &Trash, name: &OsStr, original: &Path) -> io::Result<(PathBuf, PathBuf)> {
+    let info_dir = trash.root.join("info");
+    let files_dir = trash.root.join("files");
+    fs::create_dir_all(&info_dir)?;
+    fs::create_dir_all(&files_dir)?;
+    let recorded = trash
+        .base
+        .as_ref()
+        .and_then(|base| original.strip_prefix(base).ok())
+        .unwrap_or(original);
+    let contents = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        percent_encode(recorded),
+        deletion_date()
+    );
+    for n in 1.. {
+        let mut candidate = name.to_owned();
+        if n > 1 {
+            candidate.push(format!(".{}", n));
+        }
+        let mut info_name = candidate.clone();
+        info_name.push(".trashinfo");
+        let info = info_dir.join(info_name);
+        let file = files_dir.join(&candidate);
+        if fs::symlink_metadata(&file).is_ok() {
+            continue;
+        }
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info)
+        {
+            Ok(mut handle) => {
+                handle.write_all(contents.as_bytes())?;
+                return Ok((file, info));
+            }
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    unreachable!()
+}
+
+/// Returns the trash directory that files from `dir` are moved to.
+pub fn directory(dir: &Path) -> io::Result<PathBuf> {
+    find_trash(dir).map(|trash| trash.root)
+}
+
+/// Moves `path` to the trash and returns its new location.
+pub fn trash(path: &Path) -> io::Result<PathBuf> {
+    let name = path
+        .file_name()
+        .map(OsString::from)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let original = absolute(path)?;
+    let trash = find_trash(&original)?;
+    let (file, info) = reserve(&trash, &name, &original)?;
+    if let Err(err) = rename(path, &file, false) {
+        let _ = fs::remove_file(info);
+        return Err(err);
+    }
+    Ok(file)
+}
+
+/// Moves `file`, which `trash` moved to the trash, back to `original` and
+/// removes its trash info file.
+pub fn restore(file: &Path, original: &Path) -> io::Result<()> {
+    rename(file, original, false)?;
+    if let (Some(files_dir), Some(name)) = (file.parent(), file.file_name()) {
+        let mut info_name = name.to_owned();
+        info_name.push(".trashinfo");
+        let info = parent_dir(files_dir).join("info").join(info_name);
+        let _ = fs::remove_file(info);
+    }
+    Ok(())
+}
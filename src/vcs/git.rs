@@ -0,0 +1,49 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::{check, stdout, succeeds, Vcs};
+use crate::{absolute, parent_dir};
+
+pub struct Git;
+
+fn git(dir: &Path) -> Command {
+    super::command("git", "-C", dir)
+}
+
+impl Vcs for Git {
+    fn name(&self) -> &'static str {
+        "git"
+    }
+
+    fn metadata_dir(&self, dir: &Path) -> Option<PathBuf> {
+        stdout(git(dir).args(["rev-parse", "--absolute-git-dir"])).map(From::from)
+    }
+
+    fn is_tracked(&self, path: &Path) -> bool {
+        match absolute(path) {
+            Ok(path) => succeeds(
+                git(parent_dir(&path))
+                    .args(["ls-files", "--error-unmatch", "--"])
+                    .arg(&path),
+            ),
+            Err(_) => false,
+        }
+    }
+
+    fn rename(&self, source: &Path, dest: &Path, replace: bool) -> io::Result<()> {
+        if !replace && fs::symlink_metadata(dest).is_ok() {
+            return Err(io::Error::from_raw_os_error(libc::EEXIST));
+        }
+        let source = absolute(source)?;
+        let dest = absolute(dest)?;
+        let mut command = git(parent_dir(&source));
+        command.arg("mv");
+        if replace {
+            command.arg("-f");
+        }
+        check(command.arg("--").arg(&source).arg(&dest).output()?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,45 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::{check, stdout, succeeds, Vcs};
+use crate::{absolute, parent_dir};
+
+pub struct Mercurial;
+
+fn hg(dir: &Path) -> Command {
+    super::command("hg", "--cwd", dir)
+}
+
+impl Vcs for Mercurial {
+    fn name(&self) -> &'static str {
+        "hg"
+    }
+
+    fn metadata_dir(&self, dir: &Path) -> Option<PathBuf> {
+        stdout(hg(dir).arg("root")).map(|root| Path::new(&root).join(".hg"))
+    }
+
+    fn is_tracked(&self, path: &Path) -> bool {
+        match absolute(path) {
+            Ok(path) => succeeds(hg(parent_dir(&path)).args(["files", "--"]).arg(&path)),
+            Err(_) => false,
+        }
+    }
+
+    fn rename(&self, source: &Path, dest: &Path, replace: bool) -> io::Result<()> {
+        if !replace && fs::symlink_metadata(dest).is_ok() {
+            return Err(io::Error::from_raw_os_error(libc::EEXIST));
+        }
+        let source = absolute(source)?;
+        let dest = absolute(dest)?;
+        let mut command = hg(parent_dir(&source));
+        command.arg("rename");
+        if replace {
+            command.arg("--force");
+        }
+        check(command.arg("--").arg(&source).arg(&dest).output()?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,56 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use super::{check, stdout, Vcs};
+use crate::{parent_dir, rename};
+
+/// Jujutsu has no explicit notion of renames: the working copy is tracked by
+/// taking snapshots. Files are renamed normally after which a snapshot is
+/// taken so that the rename is recorded in the working-copy commit.
+pub struct Jujutsu;
+
+fn jj(dir: &Path) -> Command {
+    let mut command = Command::new("jj");
+    command.current_dir(dir).stdin(Stdio::null());
+    command
+}
+
+/// Returns a fileset expression matching exactly the file `name`.
+fn fileset(name: &std::ffi::OsStr) -> String {
+    let name = name.to_string_lossy();
+    format!(
+        "file:\"{}\"",
+        name.replace('\\', "\\\\").replace('"', "\\\"")
+    )
+}
+
+impl Vcs for Jujutsu {
+    fn name(&self) -> &'static str {
+        "jj"
+    }
+
+    fn metadata_dir(&self, dir: &Path) -> Option<PathBuf> {
+        stdout(jj(dir).arg("root")).map(|root| Path::new(&root).join(".jj"))
+    }
+
+    fn is_tracked(&self, path: &Path) -> bool {
+        let name = match path.file_name() {
+            Some(name) => name,
+            None => return false,
+        };
+        stdout(
+            jj(parent_dir(path))
+                .args(["file", "list", "--"])
+                .arg(fileset(name)),
+        )
+        .map(|files| !files.is_empty())
+        .unwrap_or(false)
+    }
+
+    fn rename(&self, source: &Path, dest: &Path, replace: bool) -> io::Result<()> {
+        rename(source, dest, replace)?;
+        check(jj(parent_dir(dest)).arg("status").output()?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,77 @@
+//! Renaming files tracked by version control systems through the systems
+//! themselves so that their records stay consistent with the working tree.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+mod git;
+mod hg;
+mod jj;
+
+pub use git::Git;
+pub use hg::Mercurial;
+pub use jj::Jujutsu;
+
+pub trait Vcs {
+    /// Name used to select the system on the command line.
+    fn name(&self) -> &'static str;
+
+    /// Returns the directory where the repository containing `dir` keeps its
+    /// metadata, if `dir` is inside a repository.
+    fn metadata_dir(&self, dir: &Path) -> Option<PathBuf>;
+
+    /// Returns whether `path` is tracked by the repository it resides in.
+    fn is_tracked(&self, path: &Path) -> bool;
+
+    /// Renames the tracked file `source` to `dest`.
+    fn rename(&self, source: &Path, dest: &Path, replace: bool) -> io::Result<()>;
+}
+
+/// Supported systems in the order they are tried. Jujutsu comes before Git
+/// because it can share its working copy with a Git repository.
+pub const ALL: &[&dyn Vcs] = &[&Jujutsu, &Git, &Mercurial];
+
+pub fn by_name(name: &str) -> Option<&'static dyn Vcs> {
+    ALL.iter().copied().find(|vcs| vcs.name() == name)
+}
+
+/// Returns the first of `systems` that tracks `path`.
+pub fn tracking(systems: &[&'static dyn Vcs], path: &Path) -> Option<&'static dyn Vcs> {
+    systems.iter().copied().find(|vcs| vcs.is_tracked(path))
+}
+
+fn command(program: &str, dir_flag: &str, dir: &Path) -> Command {
+    let mut command = Command::new(program);
+    command.arg(dir_flag).arg(dir).stdin(Stdio::null());
+    command
+}
+
+fn check(output: Output) -> io::Result<Output> {
+    if output.status.success() {
+        Ok(output)
+    } else {
+        let message = String::from_utf8_lossy(&output.stderr);
+        Err(io::Error::other(message.trim().to_owned()))
+    }
+}
+
+/// Runs `command` and returns its standard output with the trailing newline
+/// removed if it succeeds.
+fn stdout(command: &mut Command) -> Option<String> {
+    let output = command.stderr(Stdio::null()).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text = String::from_utf8(output.stdout).ok()?;
+    Some(text.trim_end_matches('\n').to_owned())
+}
+
+fn succeeds(command: &mut Command) -> bool {
+    command
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
@@ -0,0 +1,95 @@
+//! Waiting for files to arrive in directories with inotify.
+
+use std::collections::HashMap;
+use std::ffi::{CString, OsStr};
+use std::fs;
+use std::io::{self, Read};
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Size of the fixed part of an event, before the name.
+const EVENT_SIZE: usize = mem::size_of::<libc::inotify_event>();
+
+pub struct Watcher {
+    inotify: fs::File,
+    /// The directory of each watch descriptor.
+    dirs: HashMap<libc::c_int, PathBuf>,
+}
+
+impl Watcher {
+    /// Starts watching `dirs` for files that are written or moved into them.
+    pub fn new<P>(dirs: &[P]) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC | libc::IN_NONBLOCK) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut watcher = Watcher {
+            inotify: unsafe { fs::File::from_raw_fd(fd) },
+            dirs: HashMap::new(),
+        };
+        for dir in dirs {
+            let path = CString::new(dir.as_ref().as_os_str().as_bytes())?;
+            let wd = unsafe {
+                libc::inotify_add_watch(
+                    fd,
+                    path.as_ptr(),
+                    libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO | libc::IN_ONLYDIR,
+                )
+            };
+            if wd == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            watcher.dirs.insert(wd, dir.as_ref().to_owned());
+        }
+        Ok(watcher)
+    }
+
+    /// Waits up to `timeout` for files to arrive and returns their paths. A
+    /// signal ends the wait early.
+    pub fn wait(&mut self, timeout: Duration) -> io::Result<Vec<PathBuf>> {
+        let mut poll = libc::pollfd {
+            fd: self.inotify.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+        if unsafe { libc::poll(&mut poll, 1, timeout) } == -1 {
+            let err = io::Error::last_os_error();
+            return match err.kind() {
+                io::ErrorKind::Interrupted => Ok(vec![]),
+                _ => Err(err),
+            };
+        }
+        let mut buffer = vec![0; 64 * 1024];
+        let len = match self.inotify.read(&mut buffer) {
+            Ok(len) => len,
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(vec![]),
+            Err(err) => return Err(err),
+        };
+        let mut paths = vec![];
+        let mut offset = 0;
+        while offset + EVENT_SIZE <= len {
+            let event: libc::inotify_event =
+                unsafe { std::ptr::read_unaligned(buffer[offset..].as_ptr() as *const _) };
+            let name = &buffer[offset + EVENT_SIZE..offset + EVENT_SIZE + event.len as usize];
+            offset += EVENT_SIZE + event.len as usize;
+            let name = match name.iter().position(|&byte| byte == 0) {
+                Some(end) => &name[..end],
+                None => name,
+            };
+            if let (Some(dir), false) = (self.dirs.get(&event.wd), name.is_empty()) {
+                let path = dir.join(OsStr::from_bytes(name));
+                if !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+        }
+        Ok(paths)
+    }
+}
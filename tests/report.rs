@@ -0,0 +1,25 @@
+use std::fs;
+use std::process::{Command, Stdio};
+
+#[test]
+fn report_outside_renamed_directories() {
+    let temp = tempfile::tempdir().unwrap();
+    fs::create_dir(temp.path().join("out")).unwrap();
+    fs::create_dir(temp.path().join("work")).unwrap();
+    fs::write(temp.path().join("work/a"), "").unwrap();
+    fs::write(
+        temp.path().join("plan.csv"),
+        "source,destination\nwork/a,work/b\n",
+    )
+    .unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_bulkrename"))
+        .args(["apply", "-y", "-q", "--report=out/r.csv", "plan.csv"])
+        .current_dir(temp.path())
+        .stdin(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(temp.path().join("work/b").exists());
+    let report = fs::read_to_string(temp.path().join("out/r.csv")).unwrap();
+    assert!(report.contains("work/a,work/b,renamed,"));
+}